@@ -0,0 +1,321 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use image::ImageFormat;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    detect, new_bar, AnnotatedResult, Classifier, CocoIds, ColorImage,
+    Detection, DynamicImage,
+    ImageDetections, IntegralImage, LabeledDetection, OutputFormat,
+    RenderStyle, ScoreMap, ScoreMapKind,
+};
+
+/// Settings for running detection over many images
+pub struct BatchOptions {
+    /// The directory the images with detections drawn on them and the
+    /// detections are written to
+    pub output_dir: String,
+
+    /// The formats the detections are written in
+    pub formats: Vec<OutputFormat>,
+
+    /// The ids of images and classes in a COCO annotation file used by
+    /// the coco format, if given
+    pub coco_ids: Option<CocoIds>,
+
+    /// How the detections are drawn over the images
+    pub style: RenderStyle,
+
+    /// Whether or not the annotated objects of each image (see
+    /// AnnotatedResult::annotations()) are drawn along with the
+    /// detections
+    pub ground_truth: bool,
+
+    /// The kind of score maps to save for each image, if any
+    pub score_maps: Option<ScoreMapKind>,
+
+    /// The number of images processed at once (0 for one per core)
+    pub jobs: usize,
+}
+
+/// A classifier along with the class of object it finds and the size
+/// of its window
+pub struct Model<C: Classifier> {
+    pub label: String,
+    pub classifier: C,
+    pub window: [u32; 2],
+} impl<C: Classifier> Model<C> {
+    /// Labels detections of the model with its class
+    pub fn labeled(&self, detections: Vec<Detection>) -> Vec<LabeledDetection> {
+        detections
+            .into_iter()
+            .map(|detection| {
+                LabeledDetection { label: self.label.clone(), detection }
+            })
+            .collect()
+    }
+}
+
+/// An image of a batch that could not be read
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FailedImage {
+    pub image: String,
+    pub error: String,
+}
+
+/// A summary of a batch of detections. Images that could not be read
+/// are only counted as failed
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchSummary {
+    pub num_images: usize,
+    pub num_detections: usize,
+    pub images_without_detections: usize,
+    pub detections_per_image: f64,
+    pub seconds: f64,
+    pub seconds_per_image: f64,
+    pub failed_images: Vec<FailedImage>,
+} impl BatchSummary {
+    pub fn print(&self) {
+        println!("{:-^30}", " Detection Summary ");
+        println!("Images: {}", self.num_images);
+        println!("Detections: {}", self.num_detections);
+        println!("Images Without Detections: {}", self.images_without_detections);
+        println!("Detections Per Image: {:.2}", self.detections_per_image);
+        println!(
+            "Time: {:.2}s ({:.3}s per image)",
+            self.seconds, self.seconds_per_image,
+        );
+        if !self.failed_images.is_empty() {
+            println!("Failed Images: {}", self.failed_images.len());
+            for failed in self.failed_images.iter() {
+                println!("  {}: {}", failed.image, failed.error);
+            }
+        }
+    }
+}
+
+/// Gets the images to run detection over. Each input is an image, a
+/// directory whose images are all used, or a glob pattern, and a list
+/// file holds more inputs, one per line. Directories and glob patterns
+/// only give files with the extension of an image format
+pub fn input_paths(inputs: &[&str], list: Option<&str>) -> Vec<PathBuf> {
+    let listed: Vec<String> = list.map_or(Vec::new(), |path| {
+        fs::read_to_string(path)
+            .expect("Unable to read list of images")
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(str::to_owned)
+            .collect()
+    });
+
+    let mut paths = Vec::<PathBuf>::new();
+    let all = inputs.iter().copied().chain(listed.iter().map(String::as_str));
+    for input in all {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let mut images: Vec<PathBuf> = fs::read_dir(path)
+                .expect("Unable to read image directory")
+                .map(|entry| entry.unwrap().path())
+                .filter(|p| ImageFormat::from_path(p).is_ok())
+                .collect();
+            images.sort();
+            paths.extend(images);
+        } else if input.contains(['*', '?', '[']) {
+            let matches = glob::glob(input)
+                .unwrap_or_else(|e| panic!("Invalid glob pattern {}: {}", input, e));
+            paths.extend(matches.filter_map(Result::ok).filter(|p| {
+                p.is_file() && ImageFormat::from_path(p).is_ok()
+            }));
+        } else {
+            paths.push(path.to_owned());
+        }
+    }
+    paths
+}
+
+/// Runs detection with every model over every image and writes the
+/// results to the output directory: a copy of each image with the
+/// detections drawn on it, the detections in each output format, score
+/// maps if requested and a summary. Images that cannot be read are
+/// skipped and listed in the summary
+pub fn run(
+    models: &[Model<impl Classifier + Sync>],
+    paths: &[PathBuf],
+    options: &BatchOptions,
+) -> BatchSummary {
+    let start = Instant::now();
+    fs::create_dir_all(&options.output_dir)
+        .expect("Unable to create output directory");
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(options.jobs)
+        .build()
+        .expect("Unable to start threads");
+    let names = output_names(paths);
+    let bar = new_bar(paths.len(), "Detecting Objects...");
+    let outcomes: Vec<Result<ImageDetections, String>> = pool.install(|| {
+        paths
+            .par_iter()
+            .zip(names.par_iter())
+            .map(|(path, name)| {
+                let result = detect_image(models, path, name, options);
+                bar.inc(1);
+                result
+            })
+            .collect()
+    });
+    bar.finish();
+
+    let mut results = Vec::<ImageDetections>::new();
+    let mut failed_images = Vec::<FailedImage>::new();
+    for (path, outcome) in paths.iter().zip(outcomes) {
+        match outcome {
+            Ok(result) => results.push(result),
+            Err(error) => failed_images.push(FailedImage {
+                image: path.display().to_string(),
+                error,
+            }),
+        }
+    }
+
+    let labels = labels(models);
+    let dir = Path::new(&options.output_dir);
+    for format in options.formats.iter() {
+        format.write_all(&results, &labels, options.coco_ids.as_ref(), dir);
+    }
+
+    let num_images = results.len();
+    let num_detections = results.iter().map(|r| r.detections.len()).sum();
+    let seconds = start.elapsed().as_secs_f64();
+    let summary = BatchSummary {
+        num_images,
+        num_detections,
+        images_without_detections: results
+            .iter()
+            .filter(|r| r.detections.is_empty())
+            .count(),
+        detections_per_image: num_detections as f64 / num_images.max(1) as f64,
+        seconds,
+        seconds_per_image: seconds / num_images.max(1) as f64,
+        failed_images,
+    };
+    let data = serde_json::to_string_pretty(&summary).unwrap();
+    fs::write(dir.join("summary.json"), data)
+        .expect("Unable to write to file");
+    summary
+}
+
+/// Gets the classes of objects found by a list of models in order
+fn labels(models: &[Model<impl Classifier>]) -> Vec<String> {
+    let mut labels = Vec::<String>::new();
+    for model in models {
+        if !labels.contains(&model.label) { labels.push(model.label.clone()) }
+    }
+    labels
+}
+
+/// Gets the name each image's outputs are saved under, its file name
+/// without the extension. Names shared by several images, like those
+/// of a/img.png and b/img.png or of img.png and img.jpg, are followed
+/// by a number to keep their outputs apart
+fn output_names(paths: &[PathBuf]) -> Vec<String> {
+    let mut taken = HashSet::<String>::new();
+    paths
+        .iter()
+        .map(|path| {
+            let stem = path.file_stem().map_or("image".into(), |s| {
+                s.to_string_lossy().into_owned()
+            });
+            let mut name = stem.clone();
+            let mut n = 1;
+            while taken.contains(&name) {
+                n += 1;
+                name = format!("{}_{}", stem, n);
+            }
+            taken.insert(name.clone());
+            name
+        })
+        .collect()
+}
+
+/// Runs detection with every model over one image and writes its
+/// results under its output name. The models share one integral image
+/// of the image. An error is returned if the image cannot be read
+fn detect_image(
+    models: &[Model<impl Classifier>],
+    path: &Path,
+    name: &str,
+    options: &BatchOptions,
+) -> Result<ImageDetections, String> {
+    let dir = Path::new(&options.output_dir);
+    let bytes = fs::read(path).map_err(|e| format!("Unable to read: {}", e))?;
+    let mut img = ColorImage::from(DynamicImage::from_bytes(&bytes)?);
+    let ii = IntegralImage::from(&img);
+    let mut detections = Vec::<LabeledDetection>::new();
+    for model in models {
+        let found = match options.score_maps {
+            // Score maps of each model are kept apart by their class
+            Some(kind) => {
+                let (found, maps) =
+                    ScoreMap::detect(&model.classifier, model.window, &ii, kind);
+                let prefix = if models.len() > 1 {
+                    format!("{}_{}", name, model.label)
+                } else { name.to_owned() };
+                save_score_maps(&maps, &img, &dir.join("score_maps"), &prefix);
+                found
+            },
+            None => detect(&model.classifier, model.window, &ii),
+        };
+        detections.extend(model.labeled(found));
+    }
+
+    let result = ImageDetections {
+        image: path.display().to_string(),
+        width: img.width(),
+        height: img.height(),
+        detections,
+    };
+    let labels = labels(models);
+
+    // Draw the detections over the image and then save it
+    let ground_truth = if options.ground_truth {
+        AnnotatedResult::annotations(path)
+    } else { Vec::new() };
+    options.style.render(&mut img, &result.detections, &labels, &ground_truth);
+    // Images in formats that can be read but not written (or that cannot
+    // hold the image) are saved as png instead
+    let extension = match ImageFormat::from_path(path) {
+        Ok(_) => path.extension().unwrap().to_string_lossy(),
+        Err(_) => "png".into(),
+    };
+    let saved = dir.join(format!("{}.{}", name, extension));
+    if img.save(&saved).is_err() {
+        fs::remove_file(&saved).ok();
+        img.save(dir.join(format!("{}.png", name)))
+            .map_err(|e| format!("Unable to save: {}", e))?;
+    }
+
+    // Output the detected objects in the formats with a file per image
+    for format in options.formats.iter() {
+        format.write_image(&result, name, &labels, dir);
+    }
+    Ok(result)
+}
+
+/// Saves the score map of each window size and their overlay on an
+/// image to a directory
+fn save_score_maps(maps: &[ScoreMap], img: &ColorImage, dir: &Path, stem: &str) {
+    fs::create_dir_all(dir).expect("Unable to create output directory");
+    for map in maps {
+        let [w, h] = map.window;
+        map.image().save(dir.join(format!("{}_{}x{}.png", stem, w, h))).unwrap();
+    }
+    ScoreMap::overlay(maps, img)
+        .save(dir.join(format!("{}_heatmap.png", stem)))
+        .unwrap();
+}
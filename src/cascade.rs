@@ -3,50 +3,65 @@ use super::{
     StrongClassifier,
     IntegralImageTrait,
     ImageData,
-    WeakClassifier,
-    TARGET_FALSE_POS,
-    FILTER, USE_LAYOUT, LAYOUT
+    FeaturePool,
+    LinearStage,
+    TrainingConfig,
+    WindowSize,
+    WL, WH,
 };
+use rand::{rngs::StdRng, SeedableRng};
 use serde::{Deserialize, Serialize};
 
 /// A cascade of strong classifiers
 #[derive(Deserialize, Serialize, Debug)]
 pub struct Cascade {
+    /// The width and height of the window the cascade was built for
+    #[serde(default = "default_window")]
+    window: [WindowSize; 2],
+
     /// The strong classifiers contained in the cascade
     scs: Vec<StrongClassifier>,
+
+    /// A linear classifier over HOG descriptors applied to the images
+    /// accepted by every strong classifier, if one was trained
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    linear: Option<LinearStage>,
+
+    /// The name of the class of object the cascade finds, if named
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
 } impl Cascade {
 
     /// Builds a cascade
-    pub fn build(mut set: Vec<ImageData>) -> Cascade {
-        // Get weak classifiers
-        println!("{:-^30}", " Getting Weak Classifiers ");
-        let wcs = WeakClassifier::get_all();
-        println!("Found {} possible weak classifiers", wcs.len());
-        
-        // Filter out underperforming weak classifiers if specified
-        let mut wcs = if FILTER {
-            println!("Filtering out underperforming weak classifiers");
-            WeakClassifier::filter(wcs, &mut set)
-        } else {wcs};
+    pub fn build(mut set: Vec<ImageData>, config: &TrainingConfig) -> Cascade {
+        ImageData::init_weights(&mut set);
+
+        // Get the features to build weak learners from
+        let mut pool = FeaturePool::new(config, &set);
 
         let mut scs = Vec::<StrongClassifier>::new();
-        let num_scs = if USE_LAYOUT {Some(LAYOUT.len())} else {None};
+        let num_scs = config.layout.as_ref().map(|l| l.len())
+            .or(config.max_stages);
         
-        let mut false_pos = 0.0;
+        let mut rng = StdRng::seed_from_u64(config.sample_seed);
+        let mut false_pos = 1.0;
         let mut i = 1;
         loop {
 
             // Tell user that we are building a new strong classifier
             println!(
                 "Building Strong Classifier {}{}{}", 
-                i.to_string(),
+                i,
                 num_scs.map_or("", |_| " of "),
                 num_scs.map_or("".to_owned(), |n| n.to_string()),
             );
             
             // Get the best weak classifier
-            let num_wcs = if USE_LAYOUT {Some(LAYOUT[i])} else {None};
-            let sc = StrongClassifier::build(&mut wcs, &mut set, num_wcs);
+            let num_wcs = config.layout.as_ref().map(|l| l[i - 1]);
+            let sc = StrongClassifier::build(
+                &mut pool, &mut set, num_wcs, config, &mut rng,
+            );
+            false_pos *= sc.test(&set).0;
 
             // Remove the true negatives from the training set
             set.retain(|id| id.is_object || sc.classify(&id.image));
@@ -57,17 +72,111 @@ pub struct Cascade {
                 false_pos);
             
             // Determine whether or not to break
-            let should_break = num_scs.map_or_else(
-                || false_pos <= TARGET_FALSE_POS, |n| i == n);
+            let should_break = match config.layout {
+                Some(ref layout) => i == layout.len(),
+                None => false_pos <= config.target_false_pos
+                    || config.max_stages == Some(i),
+            };
             if should_break { break }
+            if set.iter().all(|id| id.is_object) {
+                println!("No negative training images remain");
+                break
+            }
 
             i += 1;
         }
 
-        Cascade{scs}
+        // Train the final linear stage on the images that remain
+        let window = config.window().map(u32::from);
+        let linear = if !config.linear_stage {
+            None
+        } else if set.iter().all(|id| id.is_object) {
+            println!("Skipping linear stage: no negative images remain");
+            None
+        } else {
+            println!("{:-^30}", " Training Linear Stage ");
+            let stage = LinearStage::train(&set, window, config.min_detection);
+            let kept = set.iter().filter(|id| stage.classify(&id.image, window));
+            let (pos, neg) = kept.fold((0, 0), |(pos, neg), id| {
+                if id.is_object { (pos + 1, neg) } else { (pos, neg + 1) }
+            });
+            let num_pos = set.iter().filter(|id| id.is_object).count();
+            println!(
+                "Linear stage kept {} of {} objects and {} of {} others",
+                pos, num_pos, neg, set.len() - num_pos,
+            );
+            Some(stage)
+        };
+
+        let label = config.label.clone();
+        Cascade{window: config.window(), scs, linear, label}
+    }
+
+    /// Gets the width and height of the window the cascade was built
+    /// for
+    pub fn window(&self) -> [u32; 2] {
+        [u32::from(self.window[0]), u32::from(self.window[1])]
+    }
+
+    /// Gets the name of the class of object the cascade finds
+    pub fn label(&self) -> Option<&str> { self.label.as_deref() }
+
+    /// Gets the number of strong classifiers in the cascade
+    pub fn num_stages(&self) -> usize { self.scs.len() }
+
+    /// Gets the strong classifiers of the cascade in order
+    pub fn stages(&self) -> &[StrongClassifier] { &self.scs }
+
+    /// Gets the linear stage applied after the strong classifiers
+    pub fn linear_stage(&self) -> Option<&LinearStage> { self.linear.as_ref() }
+
+    /// Scores an image using only the first few strong classifiers of
+    /// the cascade. Returns the margin of the last strong classifier
+    /// used without thresholding it, or None if an earlier strong
+    /// classifier rejects the image
+    pub fn partial_score(
+        &self,
+        img: &impl IntegralImageTrait,
+        stages: usize,
+    ) -> Option<f64> {
+        let (last, rest) = self.scs[..stages].split_last()?;
+        if rest.iter().all(|sc| sc.classify(img)) {
+            Some(last.margin(img))
+        } else { None }
     }
 } impl Classifier for Cascade {
     fn classify(&self, img: &impl IntegralImageTrait) -> bool {
         self.scs.iter().all(|sc| sc.classify(img))
+            && self.linear.as_ref()
+                .is_none_or(|stage| stage.classify(img, self.window()))
+    }
+
+    /// Scores an image by the margin of the final strong classifier,
+    /// or of the linear stage if there is one
+    fn score(&self, img: &impl IntegralImageTrait) -> Option<f64> {
+        let mut margin = None;
+        for sc in self.scs.iter() {
+            let m = sc.margin(img);
+            if m < 0.0 { return None }
+            margin = Some(m);
+        }
+        if let Some(ref stage) = self.linear {
+            let m = stage.margin(img, self.window());
+            if m < 0.0 { return None }
+            margin = Some(m);
+        }
+        margin
+    }
+
+    /// Gets the fraction of the strong classifiers (and the linear
+    /// stage) an image passes before it is rejected
+    fn progress(&self, img: &impl IntegralImageTrait) -> f64 {
+        let passed = self.scs.iter().take_while(|sc| sc.classify(img)).count();
+        let total = self.scs.len() + usize::from(self.linear.is_some());
+        let linear = self.linear.as_ref().filter(|_| passed == self.scs.len())
+            .is_some_and(|stage| stage.classify(img, self.window()));
+        (passed + usize::from(linear)) as f64 / total.max(1) as f64
     }
 }
+
+fn default_window() -> [WindowSize; 2] { [WL, WH] }
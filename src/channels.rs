@@ -0,0 +1,56 @@
+use image::imageops::grayscale;
+
+use super::{gradient_channels, ColorImage};
+
+/// Gets every channel of an image in the order they are indexed by
+/// channel features: the gradient channels of the greyscale image (see
+/// gradient_channels()) followed by the L, U and V colour channels
+pub fn channel_stack(img: &ColorImage) -> Vec<Vec<u64>> {
+    let mut channels = gradient_channels(&grayscale(img));
+    channels.extend(luv_channels(img));
+    channels
+}
+
+/// Gets the L, U and V channels of an image. Each channel is scaled
+/// from its range in CIELUV to 0-255 so they can be summed like pixels
+pub fn luv_channels(img: &ColorImage) -> Vec<Vec<u64>> {
+    let mut channels = vec![Vec::new(); 3];
+    for pixel in img.pixels() {
+        let [l, u, v] = luv(pixel.0);
+        channels[0].push(scale(l, 0.0, 100.0));
+        channels[1].push(scale(u, -134.0, 220.0));
+        channels[2].push(scale(v, -140.0, 122.0));
+    }
+    channels
+}
+
+/// Scales a value from a range to 0-255
+fn scale(value: f64, min: f64, max: f64) -> u64 {
+    ((value - min) / (max - min) * 255.0).round().clamp(0.0, 255.0) as u64
+}
+
+/// Converts an sRGB pixel to CIELUV under the D65 white point
+fn luv(rgb: [u8; 3]) -> [f64; 3] {
+    // Chromaticity of the D65 white point
+    const WHITE_U: f64 = 0.197_839;
+    const WHITE_V: f64 = 0.468_342;
+
+    let [r, g, b] = rgb.map(|c| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.040_45 {
+            c / 12.92
+        } else { ((c + 0.055) / 1.055).powf(2.4) }
+    });
+    let x = 0.412_456 * r + 0.357_576 * g + 0.180_438 * b;
+    let y = 0.212_673 * r + 0.715_152 * g + 0.072_175 * b;
+    let z = 0.019_334 * r + 0.119_192 * g + 0.950_304 * b;
+
+    let l = if y > 0.008_856 {
+        116.0 * y.cbrt() - 16.0
+    } else { 903.3 * y };
+    let denom = x + 15.0 * y + 3.0 * z;
+    if denom == 0.0 { return [l, 0.0, 0.0] }
+    let u = 13.0 * l * (4.0 * x / denom - WHITE_U);
+    let v = 13.0 * l * (9.0 * y / denom - WHITE_V);
+    [l, u, v]
+}
@@ -4,9 +4,22 @@ pub trait Classifier {
     /// Classifies an image
     fn classify(&self, img: &impl IntegralImageTrait) -> bool;
 
+    /// Scores an image, returning None if the image is rejected.
+    /// Higher scores mean a more confident classification
+    fn score(&self, img: &impl IntegralImageTrait) -> Option<f64> {
+        if self.classify(img) { Some(0.0) } else { None }
+    }
+
+    /// Gets the fraction of the stages of the classifier an image passes
+    /// before it is rejected. Classifiers without stages either pass
+    /// an image or not
+    fn progress(&self, img: &impl IntegralImageTrait) -> f64 {
+        if self.classify(img) { 1.0 } else { 0.0 }
+    }
+
     /// Tests the classifier over a set of images and returns a tuple
     /// containing the false positive rate and the detection rate.
-    fn test(&self, set: &Vec<ImageData>) -> (f64, f64) {
+    fn test(&self, set: &[ImageData]) -> (f64, f64) {
         // Test the cascade over training images
         let mut correct_objects: f64 = 0.0;
         let mut correct_others: f64 = 0.0;
@@ -24,15 +37,15 @@ pub trait Classifier {
         // Return the false positive rate and the detection rate
         let fpr = (num_others - correct_others) / num_others;
         let dtr = correct_objects / num_objects;
-        return (fpr, dtr);
+        (fpr, dtr)
     }
 
     /// Validates that a classifier has a false positive rate below
     /// a target rate and a detection rate above a target rate.
-    fn validate(&self, target_fpr: f64, target_dtr: f64, set: &Vec<ImageData>)
+    fn validate(&self, target_fpr: f64, target_dtr: f64, set: &[ImageData])
     -> bool{
         let (fpr, dtr) = self.test(set);
-        return (fpr < target_fpr) && (dtr > target_dtr);
+        (fpr < target_fpr) && (dtr > target_dtr)
     }
 }
 
@@ -0,0 +1,244 @@
+use std::fs;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Selection, Template, WindowSize, COST_RATIO, FILTER, LAYOUT,
+    MAX_FALSE_POS, MIN_DETECTION, PERCENTAGE_TO_FILTER, TARGET_FALSE_POS,
+    USE_LAYOUT, WH_RATIO, WL_RATIO, WS,
+};
+
+/// Settings used when building a cascade. The defaults are taken from
+/// the constants in src/constants.rs
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct TrainingConfig {
+    /// Scale of the window (see WS)
+    pub ws: WindowSize,
+
+    /// Maximum acceptable false positive rate per layer
+    pub max_false_pos: f64,
+
+    /// Minimum acceptable detection rate per layer
+    pub min_detection: f64,
+
+    /// Target false positive rate for entire cascade
+    pub target_false_pos: f64,
+
+    /// The cost of missing an object relative to the cost of a false
+    /// alarm (see COST_RATIO)
+    pub cost_ratio: f64,
+
+    /// The number of weak classifiers in each strong classifier, if
+    /// a layout is used
+    pub layout: Option<Vec<usize>>,
+
+    /// The percentage of weak classifiers with the highest error to
+    /// filter out, if filtering is used
+    pub filter: Option<f64>,
+
+    /// Strategies used to select the weak classifiers considered when
+    /// building strong classifiers, applied after filtering
+    pub selection: Vec<Selection>,
+
+    /// The maximum number of strong classifiers in the cascade, if
+    /// limited
+    pub max_stages: Option<usize>,
+
+    /// The boosting algorithm used to build strong classifiers
+    pub boosting: Boosting,
+
+    /// The families of features weak learners are built from
+    pub features: Vec<FeatureFamily>,
+
+    /// The templates Haar-like features are generated from
+    pub templates: Vec<Template>,
+
+    /// The maximum depth of decision trees used as weak learners, if
+    /// trees are used instead of stumps
+    pub tree_depth: Option<usize>,
+
+    /// The quantile of the total weight below which the lowest weighted
+    /// images are ignored when choosing a weak learner, if weights are
+    /// trimmed
+    pub trim: Option<f64>,
+
+    /// The fraction of the weak classifiers randomly considered when
+    /// choosing each weak learner, if features are subsampled
+    pub feature_fraction: Option<f64>,
+
+    /// The seed used to subsample features
+    pub sample_seed: u64,
+
+    /// Whether or not a linear classifier over HOG descriptors is
+    /// trained as the final stage of the cascade
+    pub linear_stage: bool,
+
+    /// The name of the class of object the cascade finds, saved with
+    /// the cascade
+    pub label: Option<String>,
+} impl Default for TrainingConfig {
+    fn default() -> Self {
+        TrainingConfig {
+            ws: WS,
+            max_false_pos: MAX_FALSE_POS,
+            min_detection: MIN_DETECTION,
+            target_false_pos: TARGET_FALSE_POS,
+            cost_ratio: COST_RATIO,
+            layout: if USE_LAYOUT { Some(LAYOUT.to_vec()) } else { None },
+            filter: if FILTER { Some(PERCENTAGE_TO_FILTER) } else { None },
+            selection: Vec::new(),
+            max_stages: None,
+            boosting: Boosting::Discrete,
+            features: vec![FeatureFamily::Haar],
+            templates: Template::viola_jones(),
+            tree_depth: None,
+            trim: None,
+            feature_fraction: None,
+            sample_seed: 0,
+            linear_stage: false,
+            label: None,
+        }
+    }
+} impl TrainingConfig {
+    /// Reads a config from a json file. Missing settings are given
+    /// their default values
+    pub fn from_file(path: &str) -> TrainingConfig {
+        let data = fs::read_to_string(path)
+            .expect("Unable to read training config");
+        serde_json::from_str(&data).expect("Unable to parse training config")
+    }
+
+    /// Panics if the layout has no layers or a layer without weak
+    /// classifiers, since no cascade could be trained with it
+    pub fn check_layout(&self) {
+        if let Some(layout) = &self.layout {
+            if layout.is_empty() || layout.contains(&0) {
+                panic!("Layout must have at least one layer, each with at \
+                    least one weak classifier");
+            }
+        }
+    }
+
+    /// Gets the strategies used to select weak classifiers. Filtering
+    /// out a percentage is the same as keeping the rest
+    pub fn selection_strategies(&self) -> Vec<Selection> {
+        self.filter
+            .map(|p| Selection::TopPercent(100.0 - p))
+            .into_iter()
+            .chain(self.selection.iter().copied())
+            .collect()
+    }
+
+    /// Gets whether or not weak learners can be built from a family of
+    /// features
+    pub fn uses(&self, family: FeatureFamily) -> bool {
+        self.features.contains(&family)
+    }
+
+    /// Gets the width and height of the window
+    pub fn window(&self) -> [WindowSize; 2] {
+        [self.ws * WL_RATIO, self.ws * WH_RATIO]
+    }
+}
+
+/// Boosting algorithms that can be used to build strong classifiers
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Boosting {
+    /// Discrete AdaBoost with weak classifiers voting for or against
+    /// the object
+    Discrete,
+
+    /// Real AdaBoost with confidence-rated stumps that output a value
+    /// for each bin of feature values
+    Real,
+
+    /// Gentle AdaBoost with regression stumps that output a value on
+    /// each side of a threshold
+    Gentle,
+} impl FromStr for Boosting {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "discrete" => Ok(Boosting::Discrete),
+            "real" => Ok(Boosting::Real),
+            "gentle" => Ok(Boosting::Gentle),
+            _ => Err(format!("Unknown boosting algorithm: {}", s)),
+        }
+    }
+}
+
+/// Families of features that weak learners can be built from
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FeatureFamily {
+    /// Haar-like rectangle features
+    Haar,
+
+    /// Multi-block local binary patterns
+    Lbp,
+
+    /// Histograms of oriented gradients
+    Hog,
+
+    /// Sums of the colour and gradient channels over rectangles
+    Channels,
+} impl FeatureFamily {
+    /// Parses a comma separated list of feature families. "both" is
+    /// kept as a shorthand for Haar-like and LBP features
+    pub fn parse_list(s: &str) -> Result<Vec<FeatureFamily>, String> {
+        let mut families = Vec::<FeatureFamily>::new();
+        for name in s.split(',') {
+            let parsed = match name.trim() {
+                "both" => vec![FeatureFamily::Haar, FeatureFamily::Lbp],
+                name => vec![name.parse()?],
+            };
+            for family in parsed {
+                if !families.contains(&family) { families.push(family) }
+            }
+        }
+        Ok(families)
+    }
+} impl FromStr for FeatureFamily {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "haar" => Ok(FeatureFamily::Haar),
+            "lbp" => Ok(FeatureFamily::Lbp),
+            "hog" => Ok(FeatureFamily::Hog),
+            "channels" => Ok(FeatureFamily::Channels),
+            _ => Err(format!("Unknown feature family: {}", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes the default config with a layout
+    fn with_layout(layout: Vec<usize>) -> TrainingConfig {
+        TrainingConfig { layout: Some(layout), ..TrainingConfig::default() }
+    }
+
+    #[test]
+    fn layouts_with_weak_classifiers_are_accepted() {
+        with_layout(vec![1, 5, 15]).check_layout();
+        TrainingConfig { layout: None, ..TrainingConfig::default() }
+            .check_layout();
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one layer")]
+    fn empty_layouts_are_rejected() {
+        with_layout(Vec::new()).check_layout();
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one weak classifier")]
+    fn layers_without_weak_classifiers_are_rejected() {
+        with_layout(vec![2, 0]).check_layout();
+    }
+}
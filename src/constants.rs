@@ -1,8 +1,10 @@
 pub type WindowSize = u8;
 
 pub const WS: WindowSize = 4;
-pub const WL: WindowSize = WS * 7;
-pub const WH: WindowSize = WS * 8;
+pub const WL_RATIO: WindowSize = 7;
+pub const WH_RATIO: WindowSize = 8;
+pub const WL: WindowSize = WS * WL_RATIO;
+pub const WH: WindowSize = WS * WH_RATIO;
 pub const WL_32: u32 = WL as u32;
 pub const WH_32: u32 = WH as u32;
 
@@ -25,6 +27,9 @@ pub const CACHED_IMAGES: &str = "cache/images.json";
 /// Path to output the cascade
 pub const CASCADE: &str = "cache/cascade.json";
 
+/// Path to output the soft cascade
+pub const SOFT_CASCADE: &str = "cache/soft_cascade.json";
+
 // CONSTANTS USED IN BUILDING THE CASCADE
 /// The number of strong classifiers in the cascade
 pub const CASCADE_SIZE: usize = 4;
@@ -32,9 +37,39 @@ pub const CASCADE_SIZE: usize = 4;
 /// Maximum acceptable false positive rate per layer
 pub const MAX_FALSE_POS: f64 = 0.5;
 
+/// Minimum acceptable detection rate per layer
+pub const MIN_DETECTION: f64 = 0.99;
+
+/// The cost of missing an object relative to the cost of a false
+/// alarm when choosing weak classifiers and updating weights (1.0 for
+/// symmetric AdaBoost)
+pub const COST_RATIO: f64 = 1.0;
+
 /// Target false positive rate for entire cascade
 pub const TARGET_FALSE_POS: f64 = 0.001;
 
+/// The number of bins of feature values used by the weak learners of
+/// Real AdaBoost
+pub const REAL_BINS: usize = 16;
+
+/// The number of unsigned gradient orientations used by HOG features
+pub const HOG_BINS: usize = 9;
+
+/// The channel holding the gradient magnitude of every pixel, after
+/// the channel of each gradient orientation
+pub const MAGNITUDE_CHANNEL: usize = HOG_BINS;
+
+/// The first of the L, U and V colour channels, after the gradient
+/// magnitude channel
+pub const LUV_CHANNEL: usize = MAGNITUDE_CHANNEL + 1;
+
+/// The number of channels channel features can be built on
+pub const NUM_CHANNELS: usize = LUV_CHANNEL + 3;
+
+/// The number of columns and rows of cells a window is split into for
+/// the HOG descriptor used by linear stages
+pub const HOG_GRID: [usize; 2] = [4, 4];
+
 /// Sets whether or not to use a layout when building the cascade
 pub const USE_LAYOUT: bool = false;
 
@@ -48,3 +83,12 @@ pub const FILTER: bool = false;
 
 /// Sets the percentage of weak classifiers to filter out
 pub const PERCENTAGE_TO_FILTER: f64 = 10.0;
+
+/// The maximum number of training images used to estimate the
+/// correlation between features when removing redundant features
+pub const CORRELATION_SAMPLES: usize = 1000;
+
+/// The maximum number of the best weak classifiers considered when
+/// removing redundant features if decorrelate:C does not give one.
+/// Every pair of them may be compared, so the rest are dropped
+pub const DECORRELATION_CANDIDATES: usize = 2000;
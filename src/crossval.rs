@@ -0,0 +1,115 @@
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Cascade, Classifier, ImageData, Profiler, TrainingConfig};
+
+/// The performance of a cascade on a set of images it was not trained on
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FoldResult {
+    pub num_stages: usize,
+    pub detection_rate: f64,
+    pub false_pos_rate: f64,
+    pub features_per_window: f64,
+} impl FoldResult {
+    /// Tests a cascade over a validation set
+    pub fn new(cascade: &Cascade, validation: &[ImageData]) -> FoldResult {
+        let (false_pos_rate, detection_rate) = cascade.test(validation);
+        let mut profiler = Profiler::new(cascade);
+        for data in validation {
+            profiler.record(&data.image, Some(data.is_object));
+        }
+        FoldResult {
+            num_stages: cascade.num_stages(),
+            detection_rate,
+            false_pos_rate,
+            features_per_window: profiler.finish().avg_features_per_window,
+        }
+    }
+}
+
+/// The mean and sample variance of a value across folds
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Summary {
+    pub mean: f64,
+    pub variance: f64,
+} impl Summary {
+    pub fn new(values: impl Iterator<Item = f64> + Clone) -> Summary {
+        let n = values.clone().count() as f64;
+        let mean = values.clone().sum::<f64>() / n;
+        let variance = if n > 1.0 {
+            values.map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+        } else { 0.0 };
+        Summary { mean, variance }
+    }
+}
+
+/// Results of k-fold cross-validation of a training config
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CrossValidation {
+    pub config: TrainingConfig,
+    pub folds: Vec<FoldResult>,
+    pub detection_rate: Summary,
+    pub false_pos_rate: Summary,
+    pub features_per_window: Summary,
+} impl CrossValidation {
+    /// Splits a set of images into k folds. Objects and others are
+    /// shuffled and dealt out separately so that each fold has about
+    /// the same ratio of objects to others
+    pub fn split(set: Vec<ImageData>, k: usize, seed: u64) -> Vec<Vec<ImageData>> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (mut objects, mut others): (Vec<_>, Vec<_>) =
+            set.into_iter().partition(|data| data.is_object);
+        objects.shuffle(&mut rng);
+        others.shuffle(&mut rng);
+
+        let mut folds = vec![Vec::<ImageData>::new(); k];
+        for (i, data) in objects.into_iter().enumerate() {
+            folds[i % k].push(data);
+        }
+        for (i, data) in others.into_iter().enumerate() {
+            folds[i % k].push(data);
+        }
+        folds
+    }
+
+    /// Trains a cascade for each fold on the images of every other
+    /// fold and tests it on the images of the fold. At most `jobs`
+    /// cascades are trained at once since each one holds its own copy
+    /// of the training images and weak classifiers
+    pub fn run(
+        folds: &[Vec<ImageData>],
+        config: &TrainingConfig,
+        jobs: usize,
+    ) -> CrossValidation {
+        let indices: Vec<usize> = (0..folds.len()).collect();
+        let mut results = Vec::<FoldResult>::with_capacity(folds.len());
+        for chunk in indices.chunks(jobs.max(1)) {
+            let chunk_results: Vec<FoldResult> = chunk
+                .par_iter()
+                .map(|&i| {
+                    println!("{:-^30}", format!(" Training Fold {} ", i + 1));
+                    let train: Vec<ImageData> = folds
+                        .iter()
+                        .enumerate()
+                        .filter(|(j, _)| *j != i)
+                        .flat_map(|(_, fold)| fold.iter().cloned())
+                        .collect();
+                    let cascade = Cascade::build(train, config);
+                    FoldResult::new(&cascade, &folds[i])
+                })
+                .collect();
+            results.extend(chunk_results);
+        }
+
+        CrossValidation {
+            config: config.clone(),
+            detection_rate: Summary::new(results.iter().map(|r| r.detection_rate)),
+            false_pos_rate: Summary::new(results.iter().map(|r| r.false_pos_rate)),
+            features_per_window: Summary::new(
+                results.iter().map(|r| r.features_per_window),
+            ),
+            folds: results,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+use super::{draw_line, ColorImage, OrderedF64};
+
+/// Size in pixels of each plot and the margin around it
+const PLOT_SIZE: u32 = 400;
+const PLOT_MARGIN: u32 = 40;
+
+/// Colours used to plot the curves of different numbers of stages
+const PALETTE: [[u8; 3]; 6] = [
+    [0xD6, 0x27, 0x28],
+    [0x1F, 0x77, 0xB4],
+    [0x2C, 0xA0, 0x2C],
+    [0xFF, 0x7F, 0x0E],
+    [0x94, 0x67, 0xBD],
+    [0x8C, 0x56, 0x4B],
+];
+
+/// A point on both the ROC curve and the precision-recall curve.
+/// The recall is the same as the true positive rate
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct CurvePoint {
+    pub threshold: f64,
+    pub true_pos_rate: f64,
+    pub false_pos_rate: f64,
+    pub precision: f64,
+}
+
+/// The ROC and precision-recall curves of a cascade using a certain
+/// number of stages, found by sweeping the threshold of the last stage
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Curves {
+    pub stages: usize,
+    pub roc_auc: f64,
+    pub pr_auc: f64,
+    #[serde(skip)]
+    pub points: Vec<CurvePoint>,
+} impl Curves {
+    /// Creates the curves from the scores of a set of windows and
+    /// whether or not each window contains the object. Windows that
+    /// were rejected before the last stage have no score and are
+    /// treated as having the lowest possible score
+    pub fn new(stages: usize, scores: &[(Option<f64>, bool)]) -> Curves {
+        let num_pos = scores.iter().filter(|(_, obj)| *obj).count() as f64;
+        let num_neg = scores.len() as f64 - num_pos;
+
+        // Sort the scored windows from highest to lowest score
+        let mut ranked: Vec<(f64, bool)> = scores
+            .iter()
+            .filter_map(|(score, obj)| score.map(|s| (s, *obj)))
+            .collect();
+        ranked.sort_by_key(|(s, _)| std::cmp::Reverse(OrderedF64(*s)));
+
+        // Gets the point of a threshold from the number of true and
+        // false positives above it. The rates of an empty class are 0
+        // and the precision with nothing accepted is 1
+        let point = |threshold: f64, tp: f64, fp: f64| CurvePoint {
+            threshold,
+            true_pos_rate: if num_pos > 0.0 { tp / num_pos } else { 0.0 },
+            false_pos_rate: if num_neg > 0.0 { fp / num_neg } else { 0.0 },
+            precision: if tp + fp > 0.0 { tp / (tp + fp) } else { 1.0 },
+        };
+
+        // Lower the threshold one distinct score at a time
+        let mut points = vec![point(f64::INFINITY, 0.0, 0.0)];
+        let (mut tp, mut fp) = (0.0, 0.0);
+        for (i, (score, obj)) in ranked.iter().enumerate() {
+            if *obj { tp += 1.0 } else { fp += 1.0 }
+            let is_last = ranked.get(i + 1).is_none_or(|(s, _)| s != score);
+            if is_last { points.push(point(*score, tp, fp)) }
+        }
+
+        // Windows without a score are only accepted at the lowest
+        // threshold, along with every other window
+        if ranked.len() < scores.len() {
+            points.push(point(f64::NEG_INFINITY, num_pos, num_neg));
+        }
+
+        // Find the area under each curve
+        let mut roc_auc = 0.0;
+        let mut pr_auc = 0.0;
+        for pair in points.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            roc_auc += (b.false_pos_rate - a.false_pos_rate)
+                * (a.true_pos_rate + b.true_pos_rate) / 2.0;
+            pr_auc += (b.true_pos_rate - a.true_pos_rate) * b.precision;
+        }
+
+        Curves { stages, roc_auc, pr_auc, points }
+    }
+
+    /// Gets the highest true positive rate with a false positive rate
+    /// no greater than the given rate
+    pub fn detection_rate_at(&self, max_fpr: f64) -> f64 {
+        self.points
+            .iter()
+            .filter(|p| p.false_pos_rate <= max_fpr)
+            .map(|p| p.true_pos_rate)
+            .fold(0.0, f64::max)
+    }
+
+    /// Formats a set of curves as csv
+    pub fn to_csv(curves: &[Curves]) -> String {
+        let mut csv = String::from(
+            "stages,threshold,true_pos_rate,false_pos_rate,precision,recall\n",
+        );
+        for c in curves {
+            for p in c.points.iter() {
+                csv += &format!(
+                    "{},{},{},{},{},{}\n",
+                    c.stages, p.threshold, p.true_pos_rate,
+                    p.false_pos_rate, p.precision, p.true_pos_rate,
+                );
+            }
+        }
+        csv
+    }
+
+    /// Plots the ROC curves (left) and precision-recall curves (right)
+    /// of a set of curves
+    pub fn plot(curves: &[Curves]) -> ColorImage {
+        let panel = PLOT_SIZE + 2 * PLOT_MARGIN;
+        let mut img = ColorImage::from_pixel(
+            2 * panel, panel, Rgb::from([0xFF, 0xFF, 0xFF]),
+        );
+
+        // Converts a point on a plot to a pixel in the image
+        let to_pixel = |plot: u32, x: f64, y: f64| (
+            i64::from(plot * panel + PLOT_MARGIN)
+                + (x * f64::from(PLOT_SIZE)).round() as i64,
+            i64::from(PLOT_MARGIN + PLOT_SIZE)
+                - (y * f64::from(PLOT_SIZE)).round() as i64,
+        );
+
+        // Draw the grid and the axes of each plot
+        for plot in 0..2 {
+            for i in 0..=10 {
+                let t = f64::from(i) / 10.0;
+                let colour = if i == 0 || i == 10 {
+                    Rgb::from([0x00, 0x00, 0x00])
+                } else { Rgb::from([0xDD, 0xDD, 0xDD]) };
+                draw_line(
+                    &mut img, to_pixel(plot, t, 0.0), to_pixel(plot, t, 1.0),
+                    colour,
+                );
+                draw_line(
+                    &mut img, to_pixel(plot, 0.0, t), to_pixel(plot, 1.0, t),
+                    colour,
+                );
+            }
+        }
+
+        // Draw the line of a random classifier on the ROC plot
+        draw_line(
+            &mut img, to_pixel(0, 0.0, 0.0), to_pixel(0, 1.0, 1.0),
+            Rgb::from([0x99, 0x99, 0x99]),
+        );
+
+        // Draw the curves
+        for (i, c) in curves.iter().enumerate() {
+            let colour = Rgb::from(PALETTE[i % PALETTE.len()]);
+            for pair in c.points.windows(2) {
+                let (a, b) = (pair[0], pair[1]);
+                draw_line(
+                    &mut img,
+                    to_pixel(0, a.false_pos_rate, a.true_pos_rate),
+                    to_pixel(0, b.false_pos_rate, b.true_pos_rate),
+                    colour,
+                );
+                draw_line(
+                    &mut img,
+                    to_pixel(1, a.true_pos_rate, a.precision),
+                    to_pixel(1, b.true_pos_rate, b.precision),
+                    colour,
+                );
+            }
+        }
+        img
+    }
+}
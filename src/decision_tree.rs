@@ -0,0 +1,224 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    new_bar, Boosting, Feature, ImageData, IntegralImageTrait, OrderedF64,
+    WeakClassifier,
+};
+
+/// A node of a decision tree. Splits send images below the threshold
+/// to one node and all other images to another
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum TreeNode {
+    Split {
+        feature: Feature,
+        threshold: i64,
+        below: usize,
+        above: usize,
+    },
+    Leaf(f64),
+}
+
+/// A small decision tree over features whose leaves output real values
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DecisionTree {
+    /// The boosting algorithm the leaves were fitted for
+    boosting: Boosting,
+
+    nodes: Vec<TreeNode>,
+} impl DecisionTree {
+    /// Grows a decision tree of at most the given depth using the
+    /// features of a list of potential weak classifiers. Splits and
+    /// leaves are fitted to minimize the loss of the boosting algorithm
+    /// with the weights of objects scaled by the cost ratio
+    pub fn fit(
+        boosting: Boosting,
+        depth: usize,
+        all_wcs: &[WeakClassifier],
+        set: &[ImageData],
+        cost_ratio: f64,
+    ) -> DecisionTree {
+        let mut tree = DecisionTree { boosting, nodes: Vec::new() };
+        let eps = 1.0 / set.len() as f64;
+        let subset: Vec<&ImageData> = set.iter().collect();
+        tree.grow(depth, all_wcs, &subset, eps, cost_ratio);
+        tree
+    }
+
+    /// Adds a node fitted to a subset of the images and returns its
+    /// index
+    fn grow(
+        &mut self,
+        depth: usize,
+        all_wcs: &[WeakClassifier],
+        subset: &[&ImageData],
+        eps: f64,
+        cost_ratio: f64,
+    ) -> usize {
+        let boosting = self.boosting;
+        let (pos, neg) = sum_weights(subset.iter().copied());
+        let output = leaf_output(boosting, pos, neg, eps, cost_ratio);
+        let index = self.nodes.len();
+        self.nodes.push(TreeNode::Leaf(output));
+        if depth == 0 || pos == 0.0 || neg == 0.0 { return index }
+
+        // Find the split with the lowest loss
+        let bar = new_bar(all_wcs.len(), "Growing Decision Tree...");
+        let (feature, threshold, loss) = all_wcs
+            .par_iter()
+            .map(|wc| {
+                let (threshold, loss) =
+                    best_split(boosting, wc.feature(), subset, cost_ratio);
+                bar.inc(1);
+                (*wc.feature(), threshold, loss)
+            })
+            .min_by_key(|(_, _, loss)| OrderedF64(*loss))
+            .expect("List of weak classifiers was empty");
+        bar.finish();
+
+        // Keep the node as a leaf if splitting does not help
+        if loss >= leaf_loss(boosting, pos, neg, cost_ratio) { return index }
+        let (below, above): (Vec<&ImageData>, Vec<&ImageData>) = subset
+            .iter()
+            .partition(|data| feature.evaluate(&data.image) < threshold);
+        if below.is_empty() || above.is_empty() { return index }
+
+        let below = self.grow(depth - 1, all_wcs, &below, eps, cost_ratio);
+        let above = self.grow(depth - 1, all_wcs, &above, eps, cost_ratio);
+        self.nodes[index] = TreeNode::Split { feature, threshold, below, above };
+        index
+    }
+
+    /// Gets the output of the leaf an image falls into
+    pub fn output(&self, img: &impl IntegralImageTrait) -> f64 {
+        let mut i = 0;
+        loop {
+            match self.nodes[i] {
+                TreeNode::Leaf(output) => return output,
+                TreeNode::Split { ref feature, threshold, below, above } => {
+                    i = if feature.evaluate(img) < threshold {
+                        below
+                    } else { above };
+                },
+            }
+        }
+    }
+
+    /// Gets whether or not the tree was fitted for Discrete AdaBoost,
+    /// so that every leaf outputs either 0 or 1
+    pub fn is_discrete(&self) -> bool { self.boosting == Boosting::Discrete }
+
+    /// Gets the feature of every split of the tree
+    pub fn features(&self) -> Vec<Feature> {
+        self.nodes
+            .iter()
+            .filter_map(|node| match node {
+                TreeNode::Split { feature, .. } => Some(*feature),
+                TreeNode::Leaf(_) => None,
+            })
+            .collect()
+    }
+
+    /// Gets the largest number of features evaluated to reach a leaf
+    pub fn depth(&self) -> usize { self.depth_of(0) }
+
+    fn depth_of(&self, i: usize) -> usize {
+        match self.nodes[i] {
+            TreeNode::Leaf(_) => 0,
+            TreeNode::Split { below, above, .. } => {
+                1 + usize::max(self.depth_of(below), self.depth_of(above))
+            },
+        }
+    }
+}
+
+/// Sums the weights of the objects and of the others in a set of images
+pub fn sum_weights<'a>(set: impl Iterator<Item = &'a ImageData>) -> (f64, f64) {
+    set.fold((0.0, 0.0), |(pos, neg), data| {
+        if data.is_object {
+            (pos + data.weight, neg)
+        } else { (pos, neg + data.weight) }
+    })
+}
+
+/// Gets the loss of a leaf holding images with the given weights of
+/// objects and others. Missing an object costs `cost_ratio` times as
+/// much as a false alarm, so the weight of the objects is scaled by it
+pub fn leaf_loss(boosting: Boosting, pos: f64, neg: f64, cost_ratio: f64) -> f64 {
+    let pos = cost_ratio * pos;
+    match boosting {
+        // Weight of the misclassified images
+        Boosting::Discrete => f64::min(pos, neg),
+        // Weighted squared error of the weighted mean label
+        Boosting::Gentle if pos + neg > 0.0 => {
+            pos + neg - (pos - neg).powi(2) / (pos + neg)
+        },
+        Boosting::Gentle => 0.0,
+        // Normalization factor Z
+        Boosting::Real => 2.0 * f64::sqrt(pos * neg),
+    }
+}
+
+/// Gets the output of a leaf holding images with the given weights of
+/// objects and others, with the weight of the objects scaled by the
+/// cost ratio
+pub fn leaf_output(
+    boosting: Boosting,
+    pos: f64,
+    neg: f64,
+    eps: f64,
+    cost_ratio: f64,
+) -> f64 {
+    let pos = cost_ratio * pos;
+    match boosting {
+        Boosting::Discrete => if pos > neg { 1.0 } else { 0.0 },
+        Boosting::Gentle if pos + neg > 0.0 => (pos - neg) / (pos + neg),
+        Boosting::Gentle => 0.0,
+        Boosting::Real => 0.5 * f64::ln((pos + eps) / (neg + eps)),
+    }
+}
+
+/// Finds the threshold of a feature that splits a set of images with
+/// the lowest total loss of the two resulting leaves
+fn best_split(
+    boosting: Boosting,
+    feature: &Feature,
+    set: &[&ImageData],
+    cost_ratio: f64,
+) -> (i64, f64) {
+    best_threshold(
+        boosting,
+        set.iter()
+            .map(|data| (feature.evaluate(&data.image), *data))
+            .collect(),
+        cost_ratio,
+    )
+}
+
+/// Finds the threshold that splits images by their evaluations with
+/// the lowest total loss of the two resulting leaves, with the weights
+/// of objects scaled by the cost ratio
+pub fn best_threshold(
+    boosting: Boosting,
+    mut sorted: Vec<(i64, &ImageData)>,
+    cost_ratio: f64,
+) -> (i64, f64) {
+    let leaf_loss = |pos, neg| leaf_loss(boosting, pos, neg, cost_ratio);
+    sorted.sort_unstable_by_key(|(eval, _)| *eval);
+
+    let (total_pos, total_neg) = sum_weights(sorted.iter().map(|(_, d)| *d));
+    let (mut pos, mut neg) = (0.0, 0.0);
+    let mut best = (i64::MIN, leaf_loss(total_pos, total_neg));
+    for (i, (eval, data)) in sorted.iter().enumerate() {
+        if data.is_object { pos += data.weight } else { neg += data.weight }
+
+        // Only split between different evaluations
+        if sorted.get(i + 1).is_some_and(|(next, _)| next == eval) {
+            continue;
+        }
+        let loss = leaf_loss(pos, neg)
+            + leaf_loss(total_pos - pos, total_neg - neg);
+        if loss < best.1 { best = (eval + 1, loss); }
+    }
+    best
+}
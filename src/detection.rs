@@ -0,0 +1,369 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    heat_colour, Classifier, ColorImage, IntegralImage, IntegralImageTrait,
+    OrderedF64, Rectangle, WindowedIntegralImage,
+};
+
+/// An instance of the object found in an image
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct Detection {
+    pub rect: Rectangle<u32>,
+    pub score: f64,
+}
+
+/// A part of an image searched for objects: the windows inside a
+/// rectangle with widths in a range
+#[derive(Debug, Copy, Clone)]
+pub struct SearchRegion {
+    pub rect: Rectangle<u32>,
+    pub min_width: u32,
+    pub max_width: u32,
+} impl SearchRegion {
+    /// Gets the region covering all of an image
+    pub fn whole(ii: &IntegralImage) -> SearchRegion {
+        SearchRegion {
+            rect: Rectangle::new(0, 0, ii.width() as u32, ii.height() as u32),
+            min_width: 0,
+            max_width: u32::MAX,
+        }
+    }
+}
+
+/// Gets every window of a given size that is tested for the object in
+/// an image. The window moves across the image and grows in size
+pub fn windows(
+    ii: &IntegralImage,
+    window: [u32; 2],
+) -> impl Iterator<Item = (Rectangle<u32>, WindowedIntegralImage<'_>)> {
+    windows_in(ii, window, SearchRegion::whole(ii))
+}
+
+/// Gets the windows tested for the object in a region of an image.
+/// These are the windows of the whole image that lie inside the
+/// region and have a width in its range
+pub fn windows_in(
+    ii: &IntegralImage,
+    window: [u32; 2],
+    region: SearchRegion,
+) -> impl Iterator<Item = (Rectangle<u32>, WindowedIntegralImage<'_>)> {
+    let [wl, wh] = window;
+    let img_width = ii.width() as u32;
+    let img_height = ii.height() as u32;
+
+    // Windows must end before the last row and column of the image
+    let [x1, y1] = region.rect.top_left;
+    let x2 = region.rect.bot_right[0].min(img_width);
+    let y2 = region.rect.bot_right[1].min(img_height);
+
+    window_widths(ii, window)
+        .filter(move |w| region.min_width <= *w && *w <= region.max_width)
+        .flat_map(move |curr_width| {
+            let curr_height = curr_width * wh / wl;
+            let f = f64::from(curr_width) / f64::from(wl);
+            (x1..x2.saturating_sub(curr_width)).flat_map(move |x| {
+                (y1..y2.saturating_sub(curr_height)).map(move |y| {
+                    let img = WindowedIntegralImage {
+                        ii,
+                        x_offset: x as usize,
+                        y_offset: y as usize,
+                        f,
+                    };
+                    (Rectangle::<u32>::new(x, y, curr_width, curr_height), img)
+                })
+            })
+        })
+}
+
+/// Gets the widths of the windows tested for the object in an image,
+/// from the width of the window up to the largest that fits
+fn window_widths(
+    ii: &IntegralImage,
+    window: [u32; 2],
+) -> impl Iterator<Item = u32> {
+    let [wl, wh] = window;
+    let img_width = ii.width() as u32;
+    let img_height = ii.height() as u32;
+
+    let max_width = if (img_width / wl) < (img_height / wh) {
+        img_width
+    } else { img_height * wl / wh };
+
+    let step_size = (f64::from(wl) / 5.0).round() as usize;
+    (wl..=max_width).step_by(step_size)
+}
+
+/// This detects objects by sending a "windowed" view into the image
+/// to be evaluated by the classifier. This tests all rectangles in the
+/// image for the object
+pub fn detect(
+    classifier: &impl Classifier,
+    window: [u32; 2],
+    ii: &IntegralImage,
+) -> Vec<Detection> {
+    windows(ii, window)
+        .filter_map(|(rect, img)| {
+            classifier.score(&img).map(|score| Detection { rect, score })
+        })
+        .collect()
+}
+
+/// Detects objects in regions of an image. Windows shared by regions
+/// are only tested once. Returns the detections and the number of
+/// windows tested
+pub fn detect_in(
+    classifier: &impl Classifier,
+    window: [u32; 2],
+    ii: &IntegralImage,
+    regions: &[SearchRegion],
+) -> (Vec<Detection>, usize) {
+    let mut detections = Vec::<Detection>::new();
+
+    // The positions tested with windows of the current width
+    let mut tested = vec![false; ii.width() * ii.height()];
+    let mut positions = Vec::<usize>::new();
+    let mut num_tested = 0;
+    for width in window_widths(ii, window) {
+        let in_range = regions
+            .iter()
+            .filter(|r| r.min_width <= width && width <= r.max_width);
+        for region in in_range {
+            let sized = SearchRegion {
+                min_width: width,
+                max_width: width,
+                ..*region
+            };
+            for (rect, img) in windows_in(ii, window, sized) {
+                let [x, y] = rect.top_left.map(|v| v as usize);
+                let i = x + ii.width() * y;
+                if tested[i] { continue }
+                tested[i] = true;
+                positions.push(i);
+                if let Some(score) = classifier.score(&img) {
+                    detections.push(Detection { rect, score });
+                }
+            }
+        }
+        num_tested += positions.len();
+        positions.drain(..).for_each(|i| tested[i] = false);
+    }
+    (detections, num_tested)
+}
+
+/// Greedily removes detections that overlap a higher scoring
+/// detection by more than the given intersection over union
+pub fn non_max_suppression(
+    mut detections: Vec<Detection>,
+    max_iou: f64,
+) -> Vec<Detection> {
+    detections.sort_by_key(|d| std::cmp::Reverse(OrderedF64(d.score)));
+    let mut kept = Vec::<Detection>::new();
+    for d in detections {
+        if kept.iter().all(|k| k.rect.iou(&d.rect) <= max_iou) {
+            kept.push(d);
+        }
+    }
+    kept
+}
+
+/// What each pixel of a score map encodes about the window at its
+/// position
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ScoreMapKind {
+    /// The fraction of the stages of the classifier the window passed
+    Stages,
+
+    /// The score of the window if it was accepted
+    Score,
+} impl FromStr for ScoreMapKind {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stages" => Ok(ScoreMapKind::Stages),
+            "score" => Ok(ScoreMapKind::Score),
+            _ => Err(format!("Unknown kind of score map: {}", s)),
+        }
+    }
+}
+
+/// The value of every window of one size in an image, from 0 to 1.
+/// Each pixel holds the value of the window with its top left corner
+/// at that position
+pub struct ScoreMap {
+    /// The width and height of the windows
+    pub window: [u32; 2],
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<f64>,
+} impl ScoreMap {
+    /// Detects objects like detect() while making the score map of
+    /// every size of window tested in an image, so that each window is
+    /// only tested once. Scores are scaled so that the highest score is
+    /// 1 and the lowest score of an accepted window is SCORE_MAP_FLOOR,
+    /// leaving 0 for rejected windows
+    pub fn detect(
+        classifier: &impl Classifier,
+        window: [u32; 2],
+        ii: &IntegralImage,
+        kind: ScoreMapKind,
+    ) -> (Vec<Detection>, Vec<ScoreMap>) {
+        let mut detections = Vec::<Detection>::new();
+        let mut maps = Vec::<ScoreMap>::new();
+        for (rect, img) in windows(ii, window) {
+            let size = [rect.width(), rect.height()];
+            if maps.last().is_none_or(|m| m.window != size) {
+                let width = ii.width() as u32 - size[0];
+                let height = ii.height() as u32 - size[1];
+                maps.push(ScoreMap {
+                    window: size,
+                    width,
+                    height,
+                    values: vec![0.0; (width * height) as usize],
+                });
+            }
+            // Only windows that pass every stage are scored, and rejected
+            // windows are marked with -1 until scores are scaled
+            let (value, score) = match kind {
+                ScoreMapKind::Stages => {
+                    let progress = classifier.progress(&img);
+                    let score = if progress >= 1.0 {
+                        classifier.score(&img)
+                    } else { None };
+                    (progress, score)
+                },
+                ScoreMapKind::Score => {
+                    let score = classifier.score(&img);
+                    (score.unwrap_or(-1.0), score)
+                },
+            };
+            if let Some(score) = score {
+                detections.push(Detection { rect, score });
+            }
+            let map = maps.last_mut().unwrap();
+            let i = (rect.top_left[0] + map.width * rect.top_left[1]) as usize;
+            map.values[i] = value;
+        }
+
+        if kind == ScoreMapKind::Score {
+            let max = maps
+                .iter()
+                .flat_map(|m| m.values.iter().copied())
+                .fold(0.0, f64::max);
+            for v in maps.iter_mut().flat_map(|m| m.values.iter_mut()) {
+                *v = if *v < 0.0 {
+                    0.0
+                } else if max > 0.0 {
+                    SCORE_MAP_FLOOR + (1.0 - SCORE_MAP_FLOOR) * *v / max
+                } else { 1.0 };
+            }
+        }
+        (detections, maps)
+    }
+
+    /// Draws the score map with a heat colour for each window
+    pub fn image(&self) -> ColorImage {
+        ColorImage::from_fn(self.width.max(1), self.height.max(1), |x, y| {
+            self.values
+                .get((x + self.width * y) as usize)
+                .map_or(heat_colour(0.0), |v| heat_colour(*v))
+        })
+    }
+
+    /// Overlays the highest value of the windows centered on each pixel
+    /// over every score map on an image. Pixels are blended with their
+    /// heat colour in proportion to their value
+    pub fn overlay(maps: &[ScoreMap], img: &ColorImage) -> ColorImage {
+        let (w, h) = img.dimensions();
+        let mut heat = vec![0.0_f64; (w * h) as usize];
+        for map in maps {
+            let [cx, cy] = [map.window[0] / 2, map.window[1] / 2];
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    let i = ((x + cx) + w * (y + cy)) as usize;
+                    let v = map.values[(x + map.width * y) as usize];
+                    heat[i] = heat[i].max(v);
+                }
+            }
+        }
+
+        let mut out = img.clone();
+        for (x, y, pixel) in out.enumerate_pixels_mut() {
+            let v = heat[(x + w * y) as usize];
+            let colour = heat_colour(v);
+            let alpha = OVERLAY_ALPHA * v;
+            for c in 0..3 {
+                pixel[c] = (f64::from(pixel[c]) * (1.0 - alpha)
+                    + f64::from(colour[c]) * alpha) as u8;
+            }
+        }
+        out
+    }
+}
+
+/// The value of the lowest scoring accepted window in a score map of
+/// scores, so that it is brighter than rejected windows
+const SCORE_MAP_FLOOR: f64 = 0.25;
+
+/// How strongly the highest values of score maps are blended over an
+/// image
+const OVERLAY_ALPHA: f64 = 0.7;
+
+#[cfg(test)]
+mod tests {
+    use image::Luma;
+
+    use super::*;
+    use crate::{GreyscaleImage, Window};
+
+    /// A two stage classifier of the top left pixel of a window, which
+    /// is scored by its value
+    struct Bright;
+    impl Bright {
+        fn stages(&self, img: &impl IntegralImageTrait) -> usize {
+            let value = img.rect_sum(&Window::new(0, 0, 1, 1));
+            usize::from(value > 50) + usize::from(value > 150)
+        }
+    } impl Classifier for Bright {
+        fn classify(&self, img: &impl IntegralImageTrait) -> bool {
+            self.stages(img) == 2
+        }
+
+        fn score(&self, img: &impl IntegralImageTrait) -> Option<f64> {
+            let value = img.rect_sum(&Window::new(0, 0, 1, 1));
+            if self.classify(img) { Some(value as f64) } else { None }
+        }
+
+        fn progress(&self, img: &impl IntegralImageTrait) -> f64 {
+            self.stages(img) as f64 / 2.0
+        }
+    }
+
+    #[test]
+    fn score_maps_find_the_detections_of_detect() {
+        let ii = IntegralImage::from(&GreyscaleImage::from_fn(16, 10, |x, y| {
+            Luma([(x * 20 + y).min(255) as u8])
+        }));
+        let expected = detect(&Bright, [4, 4], &ii);
+        assert!(!expected.is_empty());
+
+        for kind in [ScoreMapKind::Stages, ScoreMapKind::Score] {
+            let (found, maps) = ScoreMap::detect(&Bright, [4, 4], &ii, kind);
+            assert_eq!(found.len(), expected.len());
+            for (a, b) in found.iter().zip(expected.iter()) {
+                assert_eq!(a.rect.top_left, b.rect.top_left);
+                assert_eq!(a.rect.bot_right, b.rect.bot_right);
+                assert_eq!(a.score, b.score);
+            }
+
+            // Windows are marked by how far they got, or by their score
+            let map = &maps[0];
+            let at = |x: u32, y: u32| map.values[(x + map.width * y) as usize];
+            assert_eq!(at(0, 0), 0.0);
+            let passed_one = if kind == ScoreMapKind::Stages { 0.5 } else { 0.0 };
+            assert_eq!(at(3, 0), passed_one);
+            assert!(at(9, 0) > 0.0);
+        }
+    }
+}
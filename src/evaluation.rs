@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+
+use super::{Detection, ImageDetections, OrderedF64, Rectangle};
+
+/// The detections and annotated objects of a single test image
+pub struct AnnotatedResult {
+    pub detections: Vec<Detection>,
+    pub ground_truth: Vec<Rectangle<u32>>,
+}
+impl AnnotatedResult {
+    /// Gets the paths of all images in a directory of annotated images.
+    /// Annotations are stored next to each image in a json file with the
+    /// same name as the image (e.g. img.png and img.json), see
+    /// annotations(). Only files with the extension of an image format
+    /// are used
+    pub fn images_in(dir: &str) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+            .expect("Unable to read test image directory")
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| ImageFormat::from_path(path).is_ok())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Reads the annotations of an image, either a json array of
+    /// rectangles or the detections saved by detect for the image.
+    /// Images without annotations are treated as containing no objects
+    pub fn annotations(image: &Path) -> Vec<Rectangle<u32>> {
+        let path = image.with_extension("json");
+        if path.exists() {
+            let data = fs::read_to_string(&path).unwrap();
+            match serde_json::from_str(&data)
+                .expect("Unable to read image annotations")
+            {
+                Annotations::Rects(rects) => rects,
+                Annotations::Detections(result) => result.detections
+                    .iter()
+                    .map(|d| d.detection.rect)
+                    .collect(),
+            }
+        } else { Vec::new() }
+    }
+}
+
+/// The formats the annotations of an image can be stored in
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Annotations {
+    Rects(Vec<Rectangle<u32>>),
+    Detections(ImageDetections),
+}
+
+/// Results of evaluating detection over a set of annotated images
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Evaluation {
+    pub num_images: usize,
+    pub true_pos: usize,
+    pub false_pos: usize,
+    pub false_neg: usize,
+    pub precision: f64,
+    pub recall: f64,
+    pub f1: f64,
+    pub average_precision: f64,
+    pub false_pos_per_image: f64,
+} impl Evaluation {
+    /// Matches detections to annotated objects, highest score first.
+    /// A detection is a true positive if its intersection over union
+    /// with an unmatched object is at least min_iou
+    pub fn new(results: &[AnnotatedResult], min_iou: f64) -> Evaluation {
+        // Scores of every detection and whether or not it was matched
+        let mut ranked = Vec::<(f64, bool)>::new();
+        let mut num_objects = 0;
+
+        for result in results {
+            num_objects += result.ground_truth.len();
+            let mut matched = vec![false; result.ground_truth.len()];
+
+            let mut detections = result.detections.clone();
+            detections.sort_by_key(|d| std::cmp::Reverse(OrderedF64(d.score)));
+            for d in detections {
+                let best = result.ground_truth
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !matched[*i])
+                    .map(|(i, gt)| (i, gt.iou(&d.rect)))
+                    .filter(|(_, iou)| *iou >= min_iou)
+                    .max_by_key(|(_, iou)| OrderedF64(*iou));
+                if let Some((i, _)) = best { matched[i] = true; }
+                ranked.push((d.score, best.is_some()));
+            }
+        }
+
+        let true_pos = ranked.iter().filter(|(_, tp)| *tp).count();
+        let false_pos = ranked.len() - true_pos;
+        let false_neg = num_objects - true_pos;
+
+        let precision = ratio(true_pos, true_pos + false_pos);
+        let recall = ratio(true_pos, num_objects);
+        let f1 = if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else { 0.0 };
+
+        ranked.sort_by_key(|(score, _)| std::cmp::Reverse(OrderedF64(*score)));
+        Evaluation {
+            num_images: results.len(),
+            true_pos,
+            false_pos,
+            false_neg,
+            precision,
+            recall,
+            f1,
+            average_precision: average_precision(&ranked, num_objects),
+            false_pos_per_image: ratio(false_pos, results.len()),
+        }
+    }
+}
+
+fn ratio(a: usize, b: usize) -> f64 {
+    if b == 0 { 0.0 } else { a as f64 / b as f64 }
+}
+
+/// Calculates the area under the interpolated precision-recall curve
+/// given detections sorted from highest to lowest score
+fn average_precision(ranked: &[(f64, bool)], num_objects: usize) -> f64 {
+    if num_objects == 0 { return 0.0 }
+
+    // Precision and recall after each detection
+    let mut tp = 0;
+    let mut points = Vec::<(f64, f64)>::with_capacity(ranked.len());
+    for (i, (_, is_tp)) in ranked.iter().enumerate() {
+        if *is_tp { tp += 1; }
+        points.push((ratio(tp, num_objects), ratio(tp, i + 1)));
+    }
+
+    // Make precision monotonically decreasing
+    for i in (0..points.len().saturating_sub(1)).rev() {
+        points[i].1 = f64::max(points[i].1, points[i + 1].1);
+    }
+
+    // Sum the area under the curve
+    let mut ap = 0.0;
+    let mut prev_recall = 0.0;
+    for (recall, precision) in points {
+        ap += (recall - prev_recall) * precision;
+        prev_recall = recall;
+    }
+    ap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LabeledDetection, OutputFormat};
+
+    #[test]
+    fn annotations_read_rectangles_and_saved_detections() {
+        let dir = std::env::temp_dir().join("object-detection-annotations");
+        fs::create_dir_all(&dir).unwrap();
+        let rects = [Rectangle::new(1, 2, 10, 12), Rectangle::new(20, 4, 8, 8)];
+        let read = |name: &str| -> Vec<[u32; 4]> {
+            AnnotatedResult::annotations(&dir.join(format!("{}.png", name)))
+                .iter()
+                .map(|r| {
+                    let ([x1, y1], [x2, y2]) = (r.top_left, r.bot_right);
+                    [x1, y1, x2, y2]
+                })
+                .collect()
+        };
+        let expected = vec![[1, 2, 11, 14], [20, 4, 28, 12]];
+
+        let data = serde_json::to_string(&rects).unwrap();
+        fs::write(dir.join("rects.json"), data).unwrap();
+        assert_eq!(read("rects"), expected);
+
+        // The json saved by detect can be used as annotations
+        let result = ImageDetections {
+            image: "detected.png".into(),
+            width: 40,
+            height: 30,
+            detections: rects
+                .iter()
+                .map(|&rect| LabeledDetection {
+                    label: "object".into(),
+                    detection: Detection { rect, score: 1.5 },
+                })
+                .collect(),
+        };
+        let labels = ["object".to_owned()];
+        OutputFormat::Json.write_image(&result, "detected", &labels, &dir);
+        assert_eq!(read("detected"), expected);
+
+        assert!(read("missing").is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    new_bar, Feature, ImageData, OrderedF64, TrainingConfig, WeakClassifier,
+    CORRELATION_SAMPLES, DECORRELATION_CANDIDATES,
+};
+
+/// A strategy for choosing which weak classifiers are considered when
+/// building a cascade. Strategies are applied in order, each one to
+/// the weak classifiers kept by the last
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    /// Keep the given number of weak classifiers with the lowest
+    /// weighted error
+    TopN(usize),
+
+    /// Keep the given percentage of weak classifiers with the lowest
+    /// weighted error
+    TopPercent(f64),
+
+    /// Remove weak classifiers whose features are more correlated than
+    /// the given amount with the features of better weak classifiers.
+    /// Only the given number of the best weak classifiers are compared
+    /// and the rest are dropped, since every pair may be compared
+    Decorrelate(f64, usize),
+
+    /// Keep the given number of weak classifiers with the lowest
+    /// weighted error of each feature type and size
+    Stratify(usize),
+} impl FromStr for Selection {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s.split_once(':')
+            .ok_or_else(|| format!("Selection must be name:value, got {}", s))?;
+        let bad_value = || format!("Invalid value for {}: {}", name, value);
+        let int = || value.parse().map_err(|_| bad_value());
+        let float = || value.parse().map_err(|_| bad_value());
+        match name {
+            "top_n" => int().map(Selection::TopN),
+            "top_percent" => float().map(Selection::TopPercent),
+            "decorrelate" => {
+                let (max, candidates) = match value.split_once(':') {
+                    Some((max, n)) => (max, n.parse().map_err(|_| bad_value())?),
+                    None => (value, DECORRELATION_CANDIDATES),
+                };
+                let max = max.parse().map_err(|_| bad_value())?;
+                Ok(Selection::Decorrelate(max, candidates))
+            },
+            "stratify" => int().map(Selection::Stratify),
+            _ => Err(format!("Unknown selection strategy: {}", name)),
+        }
+    }
+} impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::TopN(n) => write!(f, "top_n:{}", n),
+            Selection::TopPercent(p) => write!(f, "top_percent:{}", p),
+            Selection::Decorrelate(c, n) => {
+                write!(f, "decorrelate:{}:{}", c, n)
+            },
+            Selection::Stratify(n) => write!(f, "stratify:{}", n),
+        }
+    }
+}
+
+/// The number of weak classifiers before and after a strategy
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SelectionStep {
+    pub strategy: String,
+    pub before: usize,
+    pub kept: usize,
+}
+
+/// What was kept by the strategies used to select weak classifiers
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SelectionReport {
+    pub initial: usize,
+    pub kept: usize,
+    pub steps: Vec<SelectionStep>,
+
+    /// Number of kept weak classifiers of each feature type
+    pub by_type: BTreeMap<String, usize>,
+
+    /// Number of kept weak classifiers by the area of the rectangles
+    /// of their features, rounded down to a power of two
+    pub by_size: BTreeMap<u32, usize>,
+
+    /// The lowest and highest weighted error of the kept weak
+    /// classifiers
+    pub min_error: f64,
+    pub max_error: f64,
+} impl SelectionReport {
+    pub fn print(&self) {
+        println!("Weak Classifiers: {}", self.initial);
+        for step in self.steps.iter() {
+            println!(
+                "{}: kept {} of {}",
+                step.strategy, step.kept, step.before,
+            );
+        }
+        println!(
+            "Kept {} weak classifiers with errors from {:.4} to {:.4}",
+            self.kept, self.min_error, self.max_error,
+        );
+        for (kind, n) in self.by_type.iter() {
+            println!("{:>22}: {}", kind, n);
+        }
+        for (size, n) in self.by_size.iter() {
+            println!("{:>16} pixels: {}", format!("{}-{}", size, 2 * size - 1), n);
+        }
+    }
+}
+
+/// Selects weak classifiers from a list of potential weak classifiers
+/// by applying strategies in order. The weak classifiers are ranked by
+/// their weighted error over a set of images, where missing an object
+/// costs cost_ratio times as much as a false alarm. Returns the kept
+/// weak classifiers from best to worst along with a report
+pub fn select(
+    wcs: Vec<WeakClassifier>,
+    set: &[ImageData],
+    strategies: &[Selection],
+    cost_ratio: f64,
+) -> (Vec<WeakClassifier>, SelectionReport) {
+    let mut report = SelectionReport {
+        initial: wcs.len(),
+        ..SelectionReport::default()
+    };
+
+    // Rank the weak classifiers by their error
+    let mut wcs = wcs;
+    WeakClassifier::calculate_thresholds(&mut wcs, set, cost_ratio);
+    let mut ranked: Vec<(WeakClassifier, f64)> = wcs
+        .into_par_iter()
+        .map(|wc| (wc, wc.error(set, cost_ratio)))
+        .collect();
+    ranked.sort_by_key(|(_, err)| OrderedF64(*err));
+
+    for strategy in strategies {
+        let before = ranked.len();
+        match *strategy {
+            Selection::TopN(n) => ranked.truncate(n),
+            Selection::TopPercent(p) => {
+                if !(0.0..=100.0).contains(&p) {
+                    panic!("The percentage to keep must be between 0 and 100");
+                }
+                ranked.truncate((p / 100.0 * before as f64).ceil() as usize);
+            },
+            Selection::Decorrelate(max, candidates) => {
+                if before > candidates {
+                    println!(
+                        "Only comparing the best {} of {} weak classifiers \
+                        for correlation",
+                        candidates, before,
+                    );
+                }
+                ranked.truncate(candidates);
+                ranked = decorrelate(ranked, set, max);
+            },
+            Selection::Stratify(n) => {
+                let mut counts = HashMap::<(&str, u32), usize>::new();
+                ranked.retain(|(wc, _)| {
+                    let f = wc.feature();
+                    let count = counts
+                        .entry((feature_type(f), size_class(f)))
+                        .or_insert(0);
+                    *count += 1;
+                    *count <= n
+                });
+            },
+        }
+        report.steps.push(SelectionStep {
+            strategy: strategy.to_string(),
+            before,
+            kept: ranked.len(),
+        });
+    }
+
+    report.kept = ranked.len();
+    for (wc, _) in ranked.iter() {
+        let f = wc.feature();
+        *report.by_type.entry(feature_type(f).to_owned()).or_insert(0) += 1;
+        *report.by_size.entry(size_class(f)).or_insert(0) += 1;
+    }
+    report.min_error = ranked.first().map_or(0.0, |(_, err)| *err);
+    report.max_error = ranked.last().map_or(0.0, |(_, err)| *err);
+    (ranked.into_iter().map(|(wc, _)| wc).collect(), report)
+}
+
+/// Selects weak classifiers with the strategies of a training config
+/// and prints a report of what was kept. All weak classifiers are kept
+/// if the config has no strategies
+pub fn select_pool(
+    wcs: Vec<WeakClassifier>,
+    set: &[ImageData],
+    config: &TrainingConfig,
+) -> Vec<WeakClassifier> {
+    let strategies = config.selection_strategies();
+    if strategies.is_empty() { return wcs }
+
+    println!("{:-^30}", " Selecting Weak Classifiers ");
+    let (wcs, report) = select(wcs, set, &strategies, config.cost_ratio);
+    report.print();
+    wcs
+}
+
+/// Keeps each weak classifier (from best to worst) unless the
+/// evaluations of its feature are more correlated than the maximum
+/// with those of a weak classifier already kept. Correlations are
+/// estimated over at most CORRELATION_SAMPLES images
+fn decorrelate(
+    ranked: Vec<(WeakClassifier, f64)>,
+    set: &[ImageData],
+    max: f64,
+) -> Vec<(WeakClassifier, f64)> {
+    let step = set.len().div_ceil(CORRELATION_SAMPLES).max(1);
+    let samples: Vec<&ImageData> = set.iter().step_by(step).collect();
+
+    // Evaluations of a feature scaled to a mean of 0 and a norm of 1
+    // so that the dot product of two of them is their correlation
+    let standardize = |f: &Feature| -> Vec<f32> {
+        let evals: Vec<f64> = samples
+            .iter()
+            .map(|data| f.evaluate(&data.image) as f64)
+            .collect();
+        let mean = evals.iter().sum::<f64>() / evals.len() as f64;
+        let norm = evals
+            .iter()
+            .map(|e| (e - mean).powi(2))
+            .sum::<f64>()
+            .sqrt()
+            .max(f64::EPSILON);
+        evals.iter().map(|e| ((e - mean) / norm) as f32).collect()
+    };
+
+    let bar = new_bar(ranked.len(), "Removing Correlated Features...");
+    let mut kept = Vec::<(WeakClassifier, f64)>::new();
+    let mut kept_evals = Vec::<Vec<f32>>::new();
+    for (wc, err) in ranked {
+        let evals = standardize(wc.feature());
+        let correlated = kept_evals.par_iter().any(|other| {
+            let dot: f32 = evals.iter().zip(other.iter()).map(|(a, b)| a * b).sum();
+            f64::from(dot.abs()) > max
+        });
+        if !correlated {
+            kept.push((wc, err));
+            kept_evals.push(evals);
+        }
+        bar.inc(1);
+    }
+    bar.finish();
+    kept
+}
+
+/// Gets the name of the type of a feature from the layout of its
+/// rectangles
+fn feature_type(f: &Feature) -> &'static str {
+    if f.channel.is_some() { return "channel" }
+    let horizontal = f.white.0.top_left[1] == f.black.0.top_left[1];
+    match (f.white.1, f.black.1, horizontal) {
+        (None, None, true) => "horizontal two rectangle",
+        (None, None, false) => "vertical two rectangle",
+        (Some(_), None, true) => "horizontal three rectangle",
+        (Some(_), None, false) => "vertical three rectangle",
+        (None, Some(_), _) => "center surround",
+        _ => "four rectangle",
+    }
+}
+
+/// Gets the area of the rectangles of a feature rounded down to a
+/// power of two
+fn size_class(f: &Feature) -> u32 {
+    let [x1, y1] = f.black.0.top_left;
+    let [x2, y2] = f.black.0.bot_right;
+    let area = u32::from(x2 - x1) * u32::from(y2 - y1);
+    1 << (31 - area.max(1).leading_zeros())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decorrelation_candidates_are_part_of_the_strategy() {
+        let parse = |s: &str| s.parse::<Selection>().unwrap();
+        assert_eq!(
+            parse("decorrelate:0.9:500"),
+            Selection::Decorrelate(0.9, 500),
+        );
+        assert_eq!(
+            parse("decorrelate:0.9"),
+            Selection::Decorrelate(0.9, DECORRELATION_CANDIDATES),
+        );
+        assert_eq!(
+            parse("decorrelate:0.8").to_string(),
+            format!("decorrelate:0.8:{}", DECORRELATION_CANDIDATES),
+        );
+        assert!("decorrelate:0.9:many".parse::<Selection>().is_err());
+        assert!("decorrelate:high:10".parse::<Selection>().is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::Detection;
+
+/// A detection along with the name of the class of object found
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LabeledDetection {
+    pub label: String,
+
+    #[serde(flatten)]
+    pub detection: Detection,
+}
+
+/// The detections found in one image
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ImageDetections {
+    pub image: String,
+    pub width: u32,
+    pub height: u32,
+    pub detections: Vec<LabeledDetection>,
+}
+
+/// The ids of the images and categories of a COCO dataset, used to
+/// match COCO results to its annotations
+#[derive(Debug)]
+pub struct CocoIds {
+    /// The id of each image by its file name
+    images: HashMap<String, u64>,
+
+    /// The id of each category by its name
+    categories: HashMap<String, u64>,
+} impl CocoIds {
+    /// Reads the ids from a COCO annotation file. Images are matched by
+    /// their file name without any directories
+    pub fn load(path: &str) -> CocoIds {
+        #[derive(Deserialize)]
+        struct Image { id: u64, file_name: String }
+        #[derive(Deserialize)]
+        struct Category { id: u64, name: String }
+        #[derive(Deserialize)]
+        struct Dataset { images: Vec<Image>, categories: Vec<Category> }
+
+        let data = fs::read_to_string(path)
+            .expect("Unable to read COCO annotations");
+        let dataset: Dataset = serde_json::from_str(&data)
+            .expect("Unable to parse COCO annotations");
+        let images = dataset
+            .images
+            .into_iter()
+            .map(|image| (file_name(&image.file_name), image.id))
+            .collect();
+        let categories = dataset
+            .categories
+            .into_iter()
+            .map(|category| (category.name, category.id))
+            .collect();
+        CocoIds { images, categories }
+    }
+}
+
+/// The formats the detections of a batch of images can be saved in
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OutputFormat {
+    /// A json object of each image with its path, its size and the
+    /// label, rectangle and score of each detection (<name>.json)
+    Json,
+
+    /// COCO detection results, an array with the image, category, box
+    /// and score of every detection that pycocotools can load (coco.json)
+    Coco,
+
+    /// A Pascal VOC annotation of each image (<name>.xml)
+    Voc,
+
+    /// A YOLO label file of each image with the center and size of each
+    /// detection relative to the image and its score (<name>.txt),
+    /// along with the names of the classes in order (classes.txt)
+    Yolo,
+
+    /// A row for every detection of every image (detections.csv)
+    Csv,
+
+    /// A line of json with the detections of each image
+    /// (detections.jsonl)
+    Jsonl,
+} impl OutputFormat {
+    /// Parses a comma separated list of output formats
+    pub fn parse_list(s: &str) -> Result<Vec<OutputFormat>, String> {
+        let mut formats = Vec::<OutputFormat>::new();
+        for name in s.split(',') {
+            let format = name.trim().parse()?;
+            if !formats.contains(&format) { formats.push(format) }
+        }
+        Ok(formats)
+    }
+
+    /// Writes the file of one image, named <name>.<extension>, for
+    /// formats with a file per image. The class of a detection is its
+    /// position in the list of labels
+    pub fn write_image(
+        &self,
+        result: &ImageDetections,
+        name: &str,
+        labels: &[String],
+        dir: &Path,
+    ) {
+        let (file, data) = match self {
+            OutputFormat::Json => {
+                ("json", serde_json::to_string_pretty(result).unwrap())
+            },
+            OutputFormat::Voc => ("xml", to_voc(result)),
+            OutputFormat::Yolo => ("txt", to_yolo(result, labels)),
+            _ => return,
+        };
+        let path = dir.join(format!("{}.{}", name, file));
+        fs::write(path, data).expect("Unable to write to file");
+    }
+
+    /// Writes the files that hold the results of every image. COCO
+    /// results use the ids of an annotation file if one is given
+    pub fn write_all(
+        &self,
+        results: &[ImageDetections],
+        labels: &[String],
+        coco_ids: Option<&CocoIds>,
+        dir: &Path,
+    ) {
+        let (file, data) = match self {
+            OutputFormat::Coco => {
+                ("coco.json", to_coco(results, labels, coco_ids))
+            },
+            OutputFormat::Yolo => ("classes.txt", labels.join("\n") + "\n"),
+            OutputFormat::Csv => ("detections.csv", to_csv(results)),
+            OutputFormat::Jsonl => {
+                let lines = results
+                    .iter()
+                    .map(|r| serde_json::to_string(r).unwrap() + "\n")
+                    .collect();
+                ("detections.jsonl", lines)
+            },
+            _ => return,
+        };
+        fs::write(dir.join(file), data).expect("Unable to write to file");
+    }
+} impl FromStr for OutputFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(OutputFormat::Json),
+            "coco" => Ok(OutputFormat::Coco),
+            "voc" => Ok(OutputFormat::Voc),
+            "yolo" => Ok(OutputFormat::Yolo),
+            "csv" => Ok(OutputFormat::Csv),
+            "jsonl" => Ok(OutputFormat::Jsonl),
+            _ => Err(format!("Unknown output format: {}", s)),
+        }
+    }
+}
+
+/// Formats the results of every image as COCO detection results.
+/// Images and categories get their ids in the annotation file if one
+/// is given, and detections of images or classes missing from it are
+/// left out. Otherwise they are numbered from 1 in order
+fn to_coco(
+    results: &[ImageDetections],
+    labels: &[String],
+    ids: Option<&CocoIds>,
+) -> String {
+    let mut detections = Vec::new();
+    let mut left_out = 0;
+    for (i, result) in results.iter().enumerate() {
+        let image_id = match ids {
+            Some(ids) => ids.images.get(&file_name(&result.image)).copied(),
+            None => Some(i as u64 + 1),
+        };
+        for d in result.detections.iter() {
+            let category_id = match ids {
+                Some(ids) => ids.categories.get(&d.label).copied(),
+                None => Some(class_id(labels, &d.label) as u64 + 1),
+            };
+            let (image_id, category_id) = match (image_id, category_id) {
+                (Some(image_id), Some(category_id)) => (image_id, category_id),
+                _ => { left_out += 1; continue },
+            };
+            let r = d.detection.rect;
+            detections.push(json!({
+                "image_id": image_id,
+                "category_id": category_id,
+                "bbox": [r.top_left[0], r.top_left[1], r.width(), r.height()],
+                "score": d.detection.score,
+            }));
+        }
+    }
+    if left_out > 0 {
+        println!(
+            "Left {} detections of images or classes missing from the COCO \
+            annotations out of coco.json",
+            left_out,
+        );
+    }
+    serde_json::to_string_pretty(&detections).unwrap()
+}
+
+/// Formats the results of an image as a Pascal VOC annotation. The
+/// score of each detection is added to its object
+fn to_voc(result: &ImageDetections) -> String {
+    let path = Path::new(&result.image);
+    let name = |p: Option<&std::ffi::OsStr>| {
+        escape_xml(&p.map_or(String::new(), |s| s.to_string_lossy().into()))
+    };
+    let mut xml = format!(
+        "<annotation>\n\
+        \t<folder>{}</folder>\n\
+        \t<filename>{}</filename>\n\
+        \t<size>\n\
+        \t\t<width>{}</width>\n\
+        \t\t<height>{}</height>\n\
+        \t\t<depth>3</depth>\n\
+        \t</size>\n",
+        name(path.parent().and_then(Path::file_name)),
+        name(path.file_name()),
+        result.width,
+        result.height,
+    );
+    for d in result.detections.iter() {
+        let r = d.detection.rect;
+        xml += &format!(
+            "\t<object>\n\
+            \t\t<name>{}</name>\n\
+            \t\t<score>{}</score>\n\
+            \t\t<pose>Unspecified</pose>\n\
+            \t\t<truncated>0</truncated>\n\
+            \t\t<difficult>0</difficult>\n\
+            \t\t<bndbox>\n\
+            \t\t\t<xmin>{}</xmin>\n\
+            \t\t\t<ymin>{}</ymin>\n\
+            \t\t\t<xmax>{}</xmax>\n\
+            \t\t\t<ymax>{}</ymax>\n\
+            \t\t</bndbox>\n\
+            \t</object>\n",
+            escape_xml(&d.label), d.detection.score,
+            r.top_left[0], r.top_left[1], r.bot_right[0], r.bot_right[1],
+        );
+    }
+    xml + "</annotation>\n"
+}
+
+/// Formats the results of an image as a YOLO label file. Each line is
+/// the class, the center and size of the detection as fractions of
+/// the size of the image, and the score
+fn to_yolo(result: &ImageDetections, labels: &[String]) -> String {
+    let w = f64::from(result.width.max(1));
+    let h = f64::from(result.height.max(1));
+    result
+        .detections
+        .iter()
+        .map(|d| {
+            let r = d.detection.rect;
+            let cx = (f64::from(r.top_left[0]) + f64::from(r.width()) / 2.0) / w;
+            let cy = (f64::from(r.top_left[1]) + f64::from(r.height()) / 2.0) / h;
+            format!(
+                "{} {:.6} {:.6} {:.6} {:.6} {:.6}\n",
+                class_id(labels, &d.label), cx, cy,
+                f64::from(r.width()) / w, f64::from(r.height()) / h,
+                d.detection.score,
+            )
+        })
+        .collect()
+}
+
+/// Formats every detection of every image as a row of csv
+fn to_csv(results: &[ImageDetections]) -> String {
+    let mut csv = String::from(
+        "image,image_width,image_height,label,score,xmin,ymin,xmax,ymax\n",
+    );
+    for result in results.iter() {
+        for d in result.detections.iter() {
+            let r = d.detection.rect;
+            csv += &format!(
+                "{},{},{},{},{},{},{},{},{}\n",
+                escape_csv(&result.image), result.width, result.height,
+                escape_csv(&d.label), d.detection.score,
+                r.top_left[0], r.top_left[1], r.bot_right[0], r.bot_right[1],
+            );
+        }
+    }
+    csv
+}
+
+/// Gets the position of a label in the list of labels. Unknown labels
+/// are given the position after the last label
+fn class_id(labels: &[String], label: &str) -> usize {
+    labels.iter().position(|l| l == label).unwrap_or(labels.len())
+}
+
+/// Gets the file name of a path without its directories
+fn file_name(path: &str) -> String {
+    let path = Path::new(path);
+    path.file_name().map_or(String::new(), |s| s.to_string_lossy().into())
+}
+
+/// Quotes a field of csv if it contains a comma, quote or new line
+fn escape_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else { field.to_owned() }
+}
+
+/// Escapes the characters of text that have a meaning in xml
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
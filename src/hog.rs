@@ -0,0 +1,261 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    best_threshold, leaf_output, sum_weights, Boosting, GreyscaleImage,
+    ImageData, IntegralImageTrait, OrderedF64, Window, WindowSize, HOG_BINS,
+    HOG_GRID, MAGNITUDE_CHANNEL, WL_RATIO,
+};
+
+/// Gets the gradient channels of an image. There is a channel for each
+/// of the HOG_BINS unsigned orientations holding the gradient magnitude
+/// of the pixels with that orientation, followed by a channel holding
+/// the gradient magnitude of every pixel
+pub fn gradient_channels(img: &GreyscaleImage) -> Vec<Vec<u64>> {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let at = |x: i64, y: i64| {
+        let (x, y) = (x.clamp(0, w - 1) as u32, y.clamp(0, h - 1) as u32);
+        f64::from(img.get_pixel(x, y)[0])
+    };
+
+    let mut channels = vec![vec![0; (w * h) as usize]; HOG_BINS + 1];
+    for y in 0..h {
+        for x in 0..w {
+            let gx = at(x + 1, y) - at(x - 1, y);
+            let gy = at(x, y + 1) - at(x, y - 1);
+            let magnitude = f64::hypot(gx, gy).round() as u64;
+            let angle = f64::atan2(gy, gx).rem_euclid(PI);
+            let bin = ((angle / PI * HOG_BINS as f64) as usize).min(HOG_BINS - 1);
+
+            let i = (x + w * y) as usize;
+            channels[bin][i] = magnitude;
+            channels[MAGNITUDE_CHANNEL][i] = magnitude;
+        }
+    }
+    channels
+}
+
+/// The fraction of the gradient magnitude in a rectangle that has a
+/// given orientation, scaled by HOG_SCALE so it can be thresholded
+/// like other features
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct HogFeature {
+    pub rect: Window,
+    pub bin: u8,
+} impl HogFeature {
+    /// Gets the HOG features of every rectangle on a grid of cells the
+    /// size of the window scale
+    pub fn get_all(window: [WindowSize; 2]) -> Vec<HogFeature> {
+        let [wl, wh] = window;
+        let step = (wl / WL_RATIO).max(1);
+        let mut features = Vec::<HogFeature>::new();
+        for w in (step..wl).step_by(usize::from(step)) {
+            for h in (step..wh).step_by(usize::from(step)) {
+                for x in (0..(wl - w)).step_by(usize::from(step)) {
+                    for y in (0..(wh - h)).step_by(usize::from(step)) {
+                        for bin in 0..HOG_BINS as u8 {
+                            let rect = Window::new(x, y, w, h);
+                            features.push(HogFeature { rect, bin });
+                        }
+                    }
+                }
+            }
+        }
+        features
+    }
+
+    pub fn evaluate(&self, img: &impl IntegralImageTrait) -> i64 {
+        let total = img.channel_sum(MAGNITUDE_CHANNEL, &self.rect);
+        img.channel_sum(usize::from(self.bin), &self.rect) * HOG_SCALE
+            / (total + 1)
+    }
+}
+
+/// The scale of the fractions evaluated by HOG features
+const HOG_SCALE: i64 = 1024;
+
+/// A weak learner that outputs a value on each side of a threshold of
+/// a HOG feature
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HogStump {
+    hog: HogFeature,
+
+    /// The boosting algorithm the outputs were fitted for
+    boosting: Boosting,
+
+    threshold: i64,
+    below: f64,
+    above: f64,
+} impl HogStump {
+    /// Fits a HOG stump to the loss of a boosting algorithm with the
+    /// weights of objects scaled by the cost ratio. Returns the stump
+    /// and its loss
+    pub fn fit(
+        hog: &HogFeature,
+        set: &[ImageData],
+        boosting: Boosting,
+        cost_ratio: f64,
+    ) -> (HogStump, f64) {
+        let evals: Vec<(i64, &ImageData)> = set
+            .iter()
+            .map(|data| (hog.evaluate(&data.image), data))
+            .collect();
+        let (threshold, loss) = best_threshold(boosting, evals, cost_ratio);
+
+        let below = set.iter().filter(|d| hog.evaluate(&d.image) < threshold);
+        let above = set.iter().filter(|d| hog.evaluate(&d.image) >= threshold);
+        let eps = 1.0 / set.len() as f64;
+        let output = |(pos, neg)| {
+            leaf_output(boosting, pos, neg, eps, cost_ratio)
+        };
+        let stump = HogStump {
+            hog: *hog,
+            boosting,
+            threshold,
+            below: output(sum_weights(below)),
+            above: output(sum_weights(above)),
+        };
+        (stump, loss)
+    }
+
+    /// Gets the rectangle the gradients are summed over
+    pub fn rect(&self) -> Window { self.hog.rect }
+
+    /// Gets whether or not the stump was fitted for Discrete AdaBoost,
+    /// so that both outputs are either 0 or 1
+    pub fn is_discrete(&self) -> bool { self.boosting == Boosting::Discrete }
+
+    pub fn output(&self, img: &impl IntegralImageTrait) -> f64 {
+        if self.hog.evaluate(img) < self.threshold {
+            self.below
+        } else { self.above }
+    }
+}
+
+/// Gets the HOG descriptor of a window. The window is split into a
+/// HOG_GRID of cells and the orientation histograms of each 2x2 block
+/// of cells are normalized together
+pub fn descriptor(img: &impl IntegralImageTrait, window: [u32; 2]) -> Vec<f64> {
+    let [cols, rows] = HOG_GRID;
+    let cell_w = ((window[0] - 1) / cols as u32).max(1) as WindowSize;
+    let cell_h = ((window[1] - 1) / rows as u32).max(1) as WindowSize;
+    let cell = |col: usize, row: usize| -> Vec<f64> {
+        let rect = Window::new(
+            col as WindowSize * cell_w,
+            row as WindowSize * cell_h,
+            cell_w,
+            cell_h,
+        );
+        (0..HOG_BINS).map(|b| img.channel_sum(b, &rect) as f64).collect()
+    };
+
+    let mut descriptor = Vec::<f64>::new();
+    for row in 0..(rows - 1) {
+        for col in 0..(cols - 1) {
+            let block: Vec<f64> = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                .iter()
+                .flat_map(|(c, r)| cell(col + c, row + r))
+                .collect();
+            let norm = block.iter().map(|v| v * v).sum::<f64>().sqrt() + 1.0;
+            descriptor.extend(block.iter().map(|v| v / norm));
+        }
+    }
+    descriptor
+}
+
+/// A logistic regression over the HOG descriptor of a window, used as
+/// the final stage of a cascade
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LinearStage {
+    weights: Vec<f64>,
+    bias: f64,
+    threshold: f64,
+} impl LinearStage {
+    /// Trains a logistic regression by gradient descent with the
+    /// objects and others weighted equally, then lowers the threshold
+    /// until the given fraction of the objects are kept
+    pub fn train(
+        set: &[ImageData],
+        window: [u32; 2],
+        min_detection: f64,
+    ) -> LinearStage {
+        let data: Vec<(Vec<f64>, f64)> = set
+            .iter()
+            .map(|d| {
+                let y = if d.is_object { 1.0 } else { 0.0 };
+                (descriptor(&d.image, window), y)
+            })
+            .collect();
+        let num_objects = set.iter().filter(|d| d.is_object).count();
+        let num_others = set.len() - num_objects;
+        let class_weight = |y: f64| {
+            if y > 0.5 {
+                0.5 / num_objects.max(1) as f64
+            } else { 0.5 / num_others.max(1) as f64 }
+        };
+
+        let dim = data.first().map_or(0, |(x, _)| x.len());
+        let mut stage = LinearStage {
+            weights: vec![0.0; dim],
+            bias: 0.0,
+            threshold: 0.0,
+        };
+        for _ in 0..LINEAR_ITERATIONS {
+            let mut grad = vec![0.0; dim];
+            let mut grad_bias = 0.0;
+            for (x, y) in data.iter() {
+                let p = 1.0 / (1.0 + f64::exp(-stage.raw(x)));
+                let err = class_weight(*y) * (p - y);
+                grad.iter_mut().zip(x.iter()).for_each(|(g, xi)| *g += err * xi);
+                grad_bias += err;
+            }
+            for (w, g) in stage.weights.iter_mut().zip(grad.iter()) {
+                *w -= LINEAR_RATE * (g + LINEAR_L2 * *w);
+            }
+            stage.bias -= LINEAR_RATE * grad_bias;
+        }
+
+        // Lower the threshold until the detection rate is met
+        let mut scores: Vec<f64> = data
+            .iter()
+            .filter(|(_, y)| *y > 0.5)
+            .map(|(x, _)| stage.raw(x))
+            .collect();
+        scores.sort_by_key(|s| std::cmp::Reverse(OrderedF64(*s)));
+        let needed = (min_detection * scores.len() as f64).ceil() as usize;
+        stage.threshold = scores
+            .get(needed.clamp(1, scores.len().max(1)) - 1)
+            .map_or(0.0, |s| f64::min(*s, 0.0));
+        stage
+    }
+
+    /// Gets the number of values of the HOG descriptor the stage weighs
+    pub fn num_features(&self) -> usize { self.weights.len() }
+
+    /// Gets the log odds of a descriptor being the object
+    fn raw(&self, x: &[f64]) -> f64 {
+        self.weights.iter().zip(x.iter()).map(|(w, xi)| w * xi).sum::<f64>()
+            + self.bias
+    }
+
+    /// Gets the margin between the log odds of a window being the
+    /// object and the threshold of the stage
+    pub fn margin(&self, img: &impl IntegralImageTrait, window: [u32; 2]) -> f64 {
+        self.raw(&descriptor(img, window)) - self.threshold
+    }
+
+    /// Gets whether or not a window is classified as the object
+    pub fn classify(&self, img: &impl IntegralImageTrait, window: [u32; 2]) -> bool {
+        self.margin(img, window) >= 0.0
+    }
+}
+
+/// The number of gradient descent steps used to train a linear stage
+const LINEAR_ITERATIONS: usize = 500;
+
+/// The step size of gradient descent
+const LINEAR_RATE: f64 = 1.0;
+
+/// The strength of the L2 regularization of a linear stage
+const LINEAR_L2: f64 = 1e-3;
@@ -2,12 +2,18 @@ use std::path::PathBuf;
 use image::imageops::FilterType;
 use image::io::Reader as ImageReader;
 use image::{ImageBuffer, Luma, Rgb};
-use super::Rectangle;
 
 /// These are images without a set form. They can be converted to either
 /// greyscale or color images. They can also be resized
 pub struct DynamicImage(image::DynamicImage);
 impl DynamicImage {
+    /// Decodes an image from the bytes of a file in a common format
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        image::load_from_memory(bytes)
+            .map(DynamicImage)
+            .map_err(|e| format!("Unable to decode image: {}", e))
+    }
+
     pub fn resize(&self, w: u32, h: u32, f: FilterType) -> Self {
         Self(self.0.resize_to_fill(w, h, f))
     }
@@ -16,6 +22,8 @@ impl DynamicImage {
         let img = ImageReader::open(path).unwrap().decode().unwrap();
         DynamicImage(img)
     }
+} impl From<&str> for DynamicImage {
+    fn from(path: &str) -> Self { Self::from(PathBuf::from(path)) }
 }
 
 /// A wrapper over a buffer representing a color image
@@ -30,16 +38,27 @@ impl From<DynamicImage> for GreyscaleImage {
     fn from(img: DynamicImage) -> Self { img.0.to_luma8() }
 }
 
-/// Draws a rectangle over an image
-pub fn draw_rectangle(img: &mut ColorImage, r: &Rectangle<u32>) {
-    let pixel: Rgb<u8> = Rgb::from([0x88, 0x95, 0x8D]);
-    for x in r.top_left[0]..r.bot_right[0] {
-        img.put_pixel(x, r.top_left[1], pixel);
-        img.put_pixel(x, r.bot_right[1], pixel);
-    }
-    for y in r.top_left[1]..r.bot_right[1] {
-        img.put_pixel(r.top_left[0], y, pixel);
-        img.put_pixel(r.bot_right[0], y, pixel);
+/// Draws a line between two points, ignoring the parts of the line
+/// that fall outside of the image
+pub fn draw_line(
+    img: &mut ColorImage,
+    from: (i64, i64),
+    to: (i64, i64),
+    pixel: Rgb<u8>,
+) {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    loop {
+        let inside = x >= 0 && y >= 0
+            && x < i64::from(img.width()) && y < i64::from(img.height());
+        if inside { img.put_pixel(x as u32, y as u32, pixel); }
+        if (x, y) == to { break }
+        let e2 = 2 * err;
+        if e2 >= dy { err += dy; x += sx; }
+        if e2 <= dx { err += dx; y += sy; }
     }
 }
-
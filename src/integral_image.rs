@@ -1,11 +1,13 @@
 use std::fs;
+use std::sync::OnceLock;
 use rand::seq::SliceRandom;
-use image::imageops::{crop_imm, FilterType};
+use image::imageops::{crop_imm, grayscale, resize, FilterType};
+use image::{Luma, Rgb};
 use serde::{Deserialize, Serialize};
 use super::{
     new_bar, Window, 
-    WH_32, WL_32, DynamicImage, 
-    GreyscaleImage,
+    WindowSize, DynamicImage, ColorImage,
+    GreyscaleImage, OrderedF64, channel_stack,
 };
 
 /// A trait that allows both windowed and non-windowed 
@@ -14,6 +16,10 @@ pub trait IntegralImageTrait {
     /// Gets the sum of pixels within in a rectangular 
     /// region of an image
     fn rect_sum(&self, r: &Window) -> i64;
+
+    /// Gets the sum of a channel within a rectangular region of an
+    /// image (see channel_stack())
+    fn channel_sum(&self, channel: usize, r: &Window) -> i64;
     fn width(&self) -> usize;
     fn height(&self) -> usize;
 } 
@@ -23,65 +29,161 @@ pub struct IntegralImage {
     pixels: Vec<u64>,
     width: usize,
     height: usize,
+
+    /// The red, green and blue values of each pixel, if the integral
+    /// image was made from a colour image
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    colour: Vec<u8>,
+
+    /// Integral images of the channel stack, calculated the first time
+    /// a channel is used
+    #[serde(skip)]
+    channels: OnceLock<Vec<Vec<u64>>>,
 } impl From<&GreyscaleImage> for IntegralImage {
     fn from(img: &GreyscaleImage) -> Self {
-        // Calculate each pixel of the integral image
         let w = img.width() as usize;
         let h = img.height() as usize;
-        let mut pixels = Vec::<u64>::with_capacity(w * h);
-        for y in 0..h {
-            for x in 0..w {
-                
-                let mut pixel = u64::from(
-                    img.get_pixel(x as u32, y as u32)[0]);
-
-                if y != 0 {
-                    pixel += pixels[x + w * (y - 1)];
-                }
-                if x != 0 {
-                    pixel += pixels[(x - 1) + w * y];
-                }
-                if x != 0 && y != 0 {
-                    pixel -= pixels[(x - 1) + w * (y - 1)];
-                }
-                pixels.push(pixel);
-            }
+        let pixels = integrate(w, h, |x, y| {
+            u64::from(img.get_pixel(x as u32, y as u32)[0])
+        });
+        IntegralImage {
+            pixels,
+            width: w,
+            height: h,
+            colour: Vec::new(),
+            channels: OnceLock::new(),
         }
-        IntegralImage { pixels, width: w, height: h }
+    }
+} impl From<&ColorImage> for IntegralImage {
+    /// Makes an integral image of the greyscale image that keeps the
+    /// colour of each pixel for the colour channels
+    fn from(img: &ColorImage) -> Self {
+        IntegralImage {
+            colour: img.as_raw().clone(),
+            ..IntegralImage::from(&grayscale(img))
+        }
+    }
+} impl IntegralImage {
+    /// Gets the integral images of the channel stack
+    fn channels(&self) -> &[Vec<u64>] {
+        self.channels.get_or_init(|| {
+            let (w, h) = (self.width, self.height);
+            channel_stack(&self.to_colour_image())
+                .iter()
+                .map(|c| integrate(w, h, |x, y| c[x + w * y]))
+                .collect()
+        })
+    }
+
+    /// Recovers the colour image the integral image was made from.
+    /// Integral images made from greyscale images are recovered in
+    /// shades of grey
+    pub fn to_colour_image(&self) -> ColorImage {
+        let (w, h) = (self.width as u32, self.height as u32);
+        if let Some(img) = ColorImage::from_raw(w, h, self.colour.clone()) {
+            return img;
+        }
+        let grey = self.to_image();
+        ColorImage::from_fn(w, h, |x, y| {
+            let [p] = grey.get_pixel(x, y).0;
+            Rgb([p, p, p])
+        })
+    }
+
+    /// Recovers the greyscale image the integral image was made from
+    pub fn to_image(&self) -> GreyscaleImage {
+        let w = self.width;
+        let at = |x: usize, y: usize| self.pixels[x + w * y] as i64;
+        GreyscaleImage::from_fn(w as u32, self.height as u32, |x, y| {
+            let (x, y) = (x as usize, y as usize);
+            let mut pixel = at(x, y);
+            if y != 0 { pixel -= at(x, y - 1); }
+            if x != 0 { pixel -= at(x - 1, y); }
+            if x != 0 && y != 0 { pixel += at(x - 1, y - 1); }
+            Luma([pixel as u8])
+        })
     }
 } impl IntegralImageTrait for IntegralImage {
     fn rect_sum(&self, r: &Window) -> i64 {
-        let xtl = usize::from(r.top_left[0]);
-        let ytl = usize::from(r.top_left[1]);
-        let xbr = usize::from(r.bot_right[0]);
-        let ybr = usize::from(r.bot_right[1]);
-
-        self.pixels[xbr + self.width * ybr] as i64
-            - self.pixels[xbr + self.width * ytl] as i64
-            - self.pixels[xtl + self.width * ybr] as i64
-            + self.pixels[xtl + self.width * ytl] as i64
+        sum(&self.pixels, self.width, r, [0, 0])
+    }
+    fn channel_sum(&self, channel: usize, r: &Window) -> i64 {
+        sum(&self.channels()[channel], self.width, r, [0, 0])
     }
     fn width(&self) -> usize { self.width }
     fn height(&self) -> usize { self.height }
 }
 
+/// Calculates each pixel of an integral image from the values of the
+/// pixels of an image
+fn integrate(w: usize, h: usize, value: impl Fn(usize, usize) -> u64) -> Vec<u64> {
+    let mut pixels = Vec::<u64>::with_capacity(w * h);
+    for y in 0..h {
+        for x in 0..w {
+            let mut pixel = value(x, y);
+            if y != 0 {
+                pixel += pixels[x + w * (y - 1)];
+            }
+            if x != 0 {
+                pixel += pixels[(x - 1) + w * y];
+            }
+            if x != 0 && y != 0 {
+                pixel -= pixels[(x - 1) + w * (y - 1)];
+            }
+            pixels.push(pixel);
+        }
+    }
+    pixels
+}
+
+/// Gets the sum of a rectangular region of an integral image offset
+/// by a number of pixels
+fn sum(pixels: &[u64], width: usize, r: &Window, offset: [usize; 2]) -> i64 {
+    let xtl = usize::from(r.top_left[0]) + offset[0];
+    let ytl = usize::from(r.top_left[1]) + offset[1];
+    let xbr = usize::from(r.bot_right[0]) + offset[0];
+    let ybr = usize::from(r.bot_right[1]) + offset[1];
+
+    pixels[xbr + width * ybr] as i64
+        - pixels[xbr + width * ytl] as i64
+        - pixels[xtl + width * ybr] as i64
+        + pixels[xtl + width * ytl] as i64
+}
+
+/// A view of a window of an integral image that is a scale of the
+/// window the features were made for. Rectangles are scaled with the
+/// window and their sums are scaled back to the size of the rectangle
 pub struct WindowedIntegralImage<'a> {
     pub ii: &'a IntegralImage,
     pub x_offset: usize,
     pub y_offset: usize,
-    pub f: i64,
-} impl<'a> IntegralImageTrait for WindowedIntegralImage<'_> {
-    fn rect_sum(&self, r: &Window) -> i64 {
-        let xtl = usize::from(r.top_left[0]) + self.x_offset;
-        let ytl = usize::from(r.top_left[1]) + self.y_offset;
-        let xbr = usize::from(r.bot_right[0]) + self.x_offset;
-        let ybr = usize::from(r.bot_right[1]) + self.y_offset;
+    pub f: f64,
+} impl WindowedIntegralImage<'_> {
+    /// Gets the sum of a rectangle of an integral image after scaling
+    /// it with the window
+    fn scaled_sum(&self, pixels: &[u64], r: &Window) -> i64 {
+        let scale = |v: WindowSize, max: usize| {
+            ((f64::from(v) * self.f).round() as usize).min(max)
+        };
+        let (w, h) = (self.ii.width - 1, self.ii.height - 1);
+        let xtl = scale(r.top_left[0], w - self.x_offset) + self.x_offset;
+        let ytl = scale(r.top_left[1], h - self.y_offset) + self.y_offset;
+        let xbr = scale(r.bot_right[0], w - self.x_offset) + self.x_offset;
+        let ybr = scale(r.bot_right[1], h - self.y_offset) + self.y_offset;
+        let at = |x: usize, y: usize| pixels[x + self.ii.width * y] as i64;
+        let sum = at(xbr, ybr) - at(xbr, ytl) - at(xtl, ybr) + at(xtl, ytl);
 
-        (self.ii.pixels[xbr + self.ii.width * ybr] as i64
-            - self.ii.pixels[xbr + self.ii.width * ytl] as i64
-            - self.ii.pixels[xtl + self.ii.width * ybr] as i64
-            + self.ii.pixels[xtl + self.ii.width * ytl] as i64)
-            / (self.f * self.f)
+        let area = f64::from(r.bot_right[0] - r.top_left[0])
+            * f64::from(r.bot_right[1] - r.top_left[1]);
+        let scaled_area = ((xbr - xtl) * (ybr - ytl)).max(1) as f64;
+        (sum as f64 * area / scaled_area).round() as i64
+    }
+} impl IntegralImageTrait for WindowedIntegralImage<'_> {
+    fn rect_sum(&self, r: &Window) -> i64 {
+        self.scaled_sum(&self.ii.pixels, r)
+    }
+    fn channel_sum(&self, channel: usize, r: &Window) -> i64 {
+        self.scaled_sum(&self.ii.channels()[channel], r)
     }
     fn width(&self) -> usize { self.ii.width }
     fn height(&self) -> usize { self.ii.height }
@@ -94,17 +196,22 @@ pub struct ImageData {
     pub weight: f64,
     pub is_object: bool,
 } impl ImageData {
-    pub fn from_slice_dir(slice_dir: &str) -> Vec<IntegralImage> {
+    pub fn from_slice_dir(
+        slice_dir: &str,
+        window: [WindowSize; 2],
+    ) -> Vec<IntegralImage> {
+        let wl = u32::from(window[0]);
+        let wh = u32::from(window[1]);
         let mut sliced = Vec::<IntegralImage>::new();
         for img in fs::read_dir(slice_dir).unwrap() {
             let img = DynamicImage::from(img.unwrap().path());
-            let img = GreyscaleImage::from(img);
+            let img = ColorImage::from(img);
             let w = img.width();
             let h = img.height();
 
-            for x in 0..(w / WL_32) {
-                for y in 0..(h / WH_32) {
-                    let img = crop_imm(&img, x * WL_32, y * WL_32, WL_32, WH_32)
+            for x in 0..(w / wl) {
+                for y in 0..(h / wh) {
+                    let img = crop_imm(&img, x * wl, y * wh, wl, wh)
                         .to_image();
                     let image = IntegralImage::from(&img);
                     sliced.push(image);
@@ -120,9 +227,13 @@ pub struct ImageData {
         other_dir: &str, 
         slice_dir: &str,
         num_neg: usize,
+        window: [WindowSize; 2],
     ) -> Vec<Self> {
+        let wl = u32::from(window[0]);
+        let wh = u32::from(window[1]);
+
         // Slice images
-        let sliced = Self::from_slice_dir(slice_dir);
+        let sliced = Self::from_slice_dir(slice_dir, window);
         let sliced_size = num_neg - fs::read_dir(other_dir).unwrap().count(); 
         let sliced = sliced.choose_multiple(&mut rand::thread_rng(), sliced_size);
 
@@ -141,9 +252,9 @@ pub struct ImageData {
             // Open the image
             let img = DynamicImage::from(path.unwrap().path());
 
-            // Resize the image and turn it to grayscale
-            let img = img.resize(WL_32, WH_32, FilterType::Triangle);
-            let img = GreyscaleImage::from(img);
+            // Resize the image and keep its colour
+            let img = img.resize(wl, wh, FilterType::Triangle);
+            let img = ColorImage::from(img);
 
             // Convert image to Integral Image
             let image = IntegralImage::from(&img);
@@ -161,9 +272,9 @@ pub struct ImageData {
             // Open the image
             let img = DynamicImage::from(path.unwrap().path());
             
-            // Resize the image and turn it to grayscale
-            let img = img.resize(WL_32, WH_32, FilterType::Triangle);
-            let img = GreyscaleImage::from(img);
+            // Resize the image and keep its colour
+            let img = img.resize(wl, wh, FilterType::Triangle);
+            let img = ColorImage::from(img);
             
             // Convert image to Integral Image
             let image = IntegralImage::from(&img);
@@ -188,6 +299,69 @@ pub struct ImageData {
         set
     }
 
+    /// Resizes the image to a new window size
+    pub fn resized(&self, window: [WindowSize; 2]) -> ImageData {
+        let img = resize(
+            &self.image.to_colour_image(),
+            u32::from(window[0]),
+            u32::from(window[1]),
+            FilterType::Triangle,
+        );
+        ImageData {
+            image: IntegralImage::from(&img),
+            weight: self.weight,
+            is_object: self.is_object,
+        }
+    }
+
+    /// Sets the weights of a set of image data so that the objects
+    /// and the others each make up half of the total weight
+    pub fn init_weights(set: &mut [ImageData]) {
+        let num_objects = set.iter().filter(|d| d.is_object).count();
+        let num_others = set.len() - num_objects;
+        for data in set.iter_mut() {
+            data.weight = if data.is_object {
+                1.0 / (2 * num_objects) as f64
+            } else { 1.0 / (2 * num_others) as f64 };
+        }
+    }
+
+    /// Gets the weight of the image scaled by the cost of misclassifying
+    /// it. Missing an object costs `cost_ratio` times as much as a
+    /// false alarm
+    pub fn cost(&self, cost_ratio: f64) -> f64 {
+        if self.is_object { cost_ratio * self.weight } else { self.weight }
+    }
+
+    /// Multiplies the weight of the image by exp(-c * margin), where the
+    /// margin of a weak learner is positive if it classified the image
+    /// correctly and c is the cost of the class of the image. The costs
+    /// of objects and others are 2k / (k + 1) and 2 / (k + 1) for a cost
+    /// ratio k, so missed objects gain weight faster than false alarms
+    /// and detected objects lose weight faster than rejected others. A
+    /// cost ratio of 1 is the update of plain AdaBoost
+    pub fn reweight(&mut self, margin: f64, cost_ratio: f64) {
+        let cost = if self.is_object { 2.0 * cost_ratio } else { 2.0 };
+        self.weight *= f64::exp(-cost / (cost_ratio + 1.0) * margin);
+    }
+
+    /// Sorts a set of image data from the highest weight to the lowest
+    /// and returns how many images to keep so that the images whose
+    /// cumulative weight is below the given quantile of the total
+    /// weight are trimmed
+    pub fn trim(set: &mut [ImageData], quantile: f64) -> usize {
+        set.sort_by_key(|d| std::cmp::Reverse(OrderedF64(d.weight)));
+        let total: f64 = set.iter().map(|d| d.weight).sum();
+        let mut trimmed = 0.0;
+        let mut keep = set.len();
+        for data in set.iter().rev() {
+            trimmed += data.weight;
+            if trimmed >= quantile * total || keep == 1 { break }
+            keep -= 1;
+        }
+        keep
+    }
+
     /// Normalize the weights of a set of image data
     pub fn normalize_weights(set: &mut [ImageData]) {
         // Sum over the weights of all the images
@@ -199,3 +373,49 @@ pub struct ImageData {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes an integral image of a greyscale image
+    fn integral(w: u32, h: u32, value: impl Fn(u32, u32) -> u8) -> IntegralImage {
+        IntegralImage::from(&GreyscaleImage::from_fn(w, h, |x, y| {
+            Luma([value(x, y)])
+        }))
+    }
+
+    #[test]
+    fn unscaled_windows_sum_offset_rectangles() {
+        let ii = integral(12, 10, |x, y| (x * 7 + y * 3) as u8);
+        let window = WindowedIntegralImage {
+            ii: &ii, x_offset: 3, y_offset: 2, f: 1.0,
+        };
+        let r = Window::new(1, 2, 4, 3);
+        let offset = Window::new(4, 4, 4, 3);
+        assert_eq!(window.rect_sum(&r), ii.rect_sum(&offset));
+    }
+
+    #[test]
+    fn scaled_windows_match_the_image_at_its_size() {
+        // The integral image sums the pixels after the top left corner
+        // of a rectangle, so the large image repeats the pixels of the
+        // small one after the first row and column
+        let value = |x: u32, y: u32| ((x * 13 + y * 29) % 251) as u8;
+        let small = integral(8, 8, value);
+        let large = integral(16, 16, |x, y| value(x.div_ceil(2), y.div_ceil(2)));
+        let window = WindowedIntegralImage {
+            ii: &large, x_offset: 0, y_offset: 0, f: 2.0,
+        };
+        for r in [Window::new(0, 0, 7, 7), Window::new(1, 3, 2, 4)] {
+            assert_eq!(window.rect_sum(&r), small.rect_sum(&r));
+        }
+
+        // Sums are scaled back to the area of the unscaled rectangle
+        let flat = integral(20, 20, |_, _| 10);
+        let window = WindowedIntegralImage {
+            ii: &flat, x_offset: 4, y_offset: 2, f: 1.5,
+        };
+        assert_eq!(window.rect_sum(&Window::new(2, 2, 4, 6)), 10 * 4 * 6);
+    }
+}
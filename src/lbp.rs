@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    leaf_loss, leaf_output, Boosting, ImageData, IntegralImageTrait, Window,
+    WindowSize,
+};
+
+/// The number of codes a multi-block LBP feature can take
+pub const NUM_CODES: usize = 256;
+
+/// A multi-block local binary pattern (MB-LBP) feature. The feature
+/// covers a 3x3 grid of equally sized blocks and its code has a bit
+/// for each of the 8 outer blocks that is set when the sum of the
+/// block is at least the sum of the center block
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub struct LbpFeature {
+    /// The top left corner of the grid
+    pub x: WindowSize,
+    pub y: WindowSize,
+
+    /// The width and height of each block
+    pub w: WindowSize,
+    pub h: WindowSize,
+} impl LbpFeature {
+    /// Gets all multi-block LBP features that fit in a window. Like
+    /// the Haar-like features, the grid must end before the last row
+    /// and column of the window
+    pub fn get_all(window: [WindowSize; 2]) -> Vec<LbpFeature> {
+        let [wl, wh] = window;
+        let mut features = Vec::<LbpFeature>::new();
+        for w in 1..=((wl - 1) / 3) {
+            for h in 1..=((wh - 1) / 3) {
+                for x in 0..(wl - 3 * w) {
+                    for y in 0..(wh - 3 * h) {
+                        features.push(LbpFeature { x, y, w, h });
+                    }
+                }
+            }
+        }
+        features
+    }
+
+    /// Gets the block at a column and row of the grid
+    fn block(&self, col: WindowSize, row: WindowSize) -> Window {
+        Window::new(
+            self.x + col * self.w,
+            self.y + row * self.h,
+            self.w,
+            self.h,
+        )
+    }
+
+    /// Evaluates the code of the feature over a window of an integral
+    /// image
+    pub fn code(&self, img: &impl IntegralImageTrait) -> u8 {
+        // Outer blocks clockwise from the top left
+        const OUTER: [(WindowSize, WindowSize); 8] = [
+            (0, 0), (1, 0), (2, 0), (2, 1), (2, 2), (1, 2), (0, 2), (0, 1),
+        ];
+        let center = img.rect_sum(&self.block(1, 1));
+        OUTER.iter().enumerate().fold(0, |code, (bit, &(col, row))| {
+            if img.rect_sum(&self.block(col, row)) >= center {
+                code | (1 << bit)
+            } else { code }
+        })
+    }
+}
+
+/// A weak learner that looks up its output for the code of a
+/// multi-block LBP feature
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LbpStump {
+    feature: LbpFeature,
+
+    /// The boosting algorithm the outputs were fitted for
+    boosting: Boosting,
+
+    outputs: Vec<f64>,
+} impl LbpStump {
+    /// Fits the output for each code to the weights of the objects and
+    /// others with that code. The weights of objects are scaled by the
+    /// cost ratio. Returns the stump and its loss
+    pub fn fit(
+        feature: &LbpFeature,
+        set: &[ImageData],
+        boosting: Boosting,
+        cost_ratio: f64,
+    ) -> (LbpStump, f64) {
+        let mut pos = [0.0; NUM_CODES];
+        let mut neg = [0.0; NUM_CODES];
+        for data in set {
+            let code = usize::from(feature.code(&data.image));
+            if data.is_object {
+                pos[code] += data.weight;
+            } else {
+                neg[code] += data.weight;
+            }
+        }
+
+        let eps = 1.0 / set.len() as f64;
+        let stump = LbpStump {
+            feature: *feature,
+            boosting,
+            outputs: (0..NUM_CODES)
+                .map(|c| leaf_output(boosting, pos[c], neg[c], eps, cost_ratio))
+                .collect(),
+        };
+        let loss = (0..NUM_CODES)
+            .map(|c| leaf_loss(boosting, pos[c], neg[c], cost_ratio))
+            .sum();
+        (stump, loss)
+    }
+
+    /// Gets the blocks of the feature, paired with whether they are the
+    /// center block
+    pub fn blocks(&self) -> Vec<(Window, bool)> {
+        (0..3)
+            .flat_map(|row| (0..3).map(move |col| (col, row)))
+            .map(|(col, row)| (self.feature.block(col, row), (col, row) == (1, 1)))
+            .collect()
+    }
+
+    /// Gets whether or not the stump was fitted for Discrete AdaBoost,
+    /// so that every output is either 0 or 1
+    pub fn is_discrete(&self) -> bool { self.boosting == Boosting::Discrete }
+
+    pub fn output(&self, img: &impl IntegralImageTrait) -> f64 {
+        self.outputs[usize::from(self.feature.code(img))]
+    }
+}
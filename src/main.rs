@@ -3,12 +3,36 @@ mod images;
 mod primitives;
 mod strong_classifier;
 mod weak_classifier;
+mod weak_learner;
+mod decision_tree;
 mod classifier;
 mod cascade;
 mod integral_image;
+mod detection;
+mod evaluation;
+mod curves;
+mod stats;
+mod config;
+mod crossval;
+mod sweep;
+mod soft_cascade;
+mod feature_selection;
+mod lbp;
+mod channels;
+mod templates;
+mod visualize;
+mod batch;
+mod formats;
+mod render;
+mod tracking;
+mod sequence;
+mod serve;
+mod worker;
+mod hog;
 
 use std::{fs, path::Path};
 use clap::{load_yaml, App, AppSettings};
+use rayon::prelude::*;
 
 pub use integral_image::{
     ImageData, IntegralImage, 
@@ -19,13 +43,44 @@ pub use constants::*;
 pub use primitives::*;
 pub use strong_classifier::StrongClassifier;
 pub use weak_classifier::WeakClassifier;
+pub use weak_learner::{FeaturePool, WeakLearner};
+pub use decision_tree::{
+    best_threshold, leaf_loss, leaf_output, sum_weights, DecisionTree,
+};
+pub use lbp::{LbpFeature, LbpStump};
+pub use hog::{gradient_channels, HogFeature, HogStump, LinearStage};
+pub use channels::channel_stack;
+pub use templates::{Shape, Template};
+pub use visualize::{heat_colour, Visualizer};
+pub use batch::{BatchOptions, FailedImage, Model};
+pub use formats::{
+    CocoIds, ImageDetections, LabeledDetection, OutputFormat,
+};
+pub use render::{draw_box, draw_text, fill_box, RenderStyle};
+pub use tracking::{Prediction, TrackedDetection, Tracker};
+pub use serve::{detect_classes, DetectResponse, ServeOptions};
+pub use sequence::{
+    Acceleration, FrameSource, SequenceOptions, StreamFormat,
+};
 pub use classifier::Classifier;
 pub use cascade::Cascade;
+pub use detection::{
+    detect, detect_in, non_max_suppression, windows, windows_in, Detection,
+    ScoreMap, ScoreMapKind, SearchRegion,
+};
+pub use evaluation::{AnnotatedResult, Evaluation};
+pub use curves::Curves;
+pub use stats::{CascadeStats, Profiler};
+pub use config::{Boosting, FeatureFamily, TrainingConfig};
+pub use crossval::CrossValidation;
+pub use sweep::{Leaderboard, SweepRanges};
+pub use soft_cascade::{SoftCascade, SpeedReport};
+pub use feature_selection::{select_pool, Selection};
 pub use images::{
     ColorImage, 
     GreyscaleImage, 
     DynamicImage,
-    draw_rectangle,
+    draw_line,
 };
 
 fn main() {
@@ -38,9 +93,20 @@ fn main() {
     // Run the specified subcommand
     match app.subcommand() {
         ("process_images", Some(_)) => process_images(),
-        ("cascade", Some(_)) => cascade(),
+        ("cascade", Some(m)) => cascade(m),
+        ("soft_cascade", Some(m)) => soft_cascade(m),
+        ("select", Some(m)) => select(m),
         ("test", Some(_)) => test(),
-        ("detect", Some(m)) => detect(m),
+        ("detect", Some(m)) => detect_objects(m),
+        ("detect_sequence", Some(m)) => detect_sequence(m),
+        ("serve", Some(m)) => serve(m),
+        ("worker", Some(m)) => worker(m),
+        ("evaluate", Some(m)) => evaluate(m),
+        ("curves", Some(m)) => curves(m),
+        ("visualize", Some(m)) => visualize(m),
+        ("stats", Some(m)) => stats(m),
+        ("crossval", Some(m)) => crossval(m),
+        ("sweep", Some(m)) => sweep(m),
         _ => println!("Incorrect subcommand"),
     }
 }
@@ -50,7 +116,7 @@ fn process_images() {
     // Find and process images
     println!("Training Image:");
     let set = ImageData::from_dirs(
-        OBJECT_DIR, OTHER_DIR, SLICE_DIR, NUM_NEG,
+        OBJECT_DIR, OTHER_DIR, SLICE_DIR, NUM_NEG, [WL, WH],
     );
     println!("Processed {} images", set.len());
 
@@ -60,53 +126,121 @@ fn process_images() {
 }
 
 /// Builds the cascade
-fn cascade() {
-    // Get training images from cache or process raw images if cache
-    // is empty
-    let set: Vec<ImageData> = {
-        if Path::new(CACHED_IMAGES).exists() {
-            let data = std::fs::read_to_string(CACHED_IMAGES).unwrap();
-            serde_json::from_str(&data).expect("Unable to read cached images")
-        } else {
-            println!("Training image data not found in cache");
-            return;
-        }
+fn cascade(m: &clap::ArgMatches) {
+    // Get training images from cache or process raw images if the
+    // cached images are the wrong size
+    let config = training_config(m);
+    if m.is_present("dry_run") {
+        FeaturePool::print_counts(&config);
+        return;
+    }
+    // A class of object other than the cached one is trained on the
+    // images in its own directory
+    let set = match m.value_of("objects") {
+        Some(dir) => ImageData::from_dirs(
+            dir, OTHER_DIR, SLICE_DIR, NUM_NEG, config.window(),
+        ),
+        None => match training_images(&config) {
+            Some(set) => set,
+            None => return,
+        },
     };
 
     println!("{:-^30}", " Building Cascade ");
-    let cascade = Cascade::build(set);
+    let cascade = Cascade::build(set, &config);
 
     // Output the data
-    println!("Saving cascade to {}", CASCADE);
+    let path = m.value_of("output").unwrap_or(CASCADE);
+    println!("Saving cascade to {}", path);
     let data = serde_json::to_string_pretty(&cascade).unwrap();
-    fs::write(CASCADE, &data).expect("Unable to write to file");
+    fs::write(path, &data).expect("Unable to write to file");
+}
+
+/// Builds a soft cascade and calibrates its rejection thresholds
+fn soft_cascade(m: &clap::ArgMatches) {
+    let config = training_config(m);
+    let set = match training_images(&config) {
+        Some(set) => set,
+        None => return,
+    };
+
+    let length: usize = m.value_of("length").unwrap().parse()
+        .expect("Length must be an integer");
+    let target_detection: f64 = m.value_of("target_detection")
+        .map_or(config.min_detection, |d| {
+            d.parse().expect("Target detection rate must be a number")
+        });
+    let validation: f64 = m.value_of("validation").unwrap().parse()
+        .expect("Validation fraction must be a number");
+    let seed: u64 = m.value_of("seed").unwrap().parse()
+        .expect("Seed must be an integer");
+    let (train, valid) = Leaderboard::split(set, validation, seed);
+
+    println!("{:-^30}", " Building Soft Cascade ");
+    let soft = match SoftCascade::build(
+        train.clone(), &valid, length, target_detection, &config,
+    ) {
+        Ok(soft) => soft,
+        Err(e) => {
+            println!("{}", e);
+            return;
+        },
+    };
+    let mut reports = vec![SpeedReport::soft(&soft, &valid)];
+
+    // Train a cascade on the same images to compare against
+    if m.is_present("compare") {
+        println!("{:-^30}", " Building Cascade ");
+        let cascade = Cascade::build(train, &config);
+        reports.push(SpeedReport::staged(&cascade, &valid));
+    }
+
+    println!("{:-^30}", " Validation ");
+    reports.iter().for_each(SpeedReport::print);
+    let data = serde_json::to_string_pretty(&reports).unwrap();
+    fs::write("output/soft_cascade.json", &data)
+        .expect("Unable to write to file");
+
+    // Output the soft cascade
+    println!("Saving soft cascade to {}", SOFT_CASCADE);
+    let data = serde_json::to_string_pretty(&soft).unwrap();
+    fs::write(SOFT_CASCADE, &data).expect("Unable to write to file");
+}
+
+/// Selects weak classifiers with the strategies of a training config
+/// without building a cascade
+fn select(m: &clap::ArgMatches) {
+    let config = training_config(m);
+    let mut set = match training_images(&config) {
+        Some(set) => set,
+        None => return,
+    };
+    ImageData::init_weights(&mut set);
+
+    let wcs = FeaturePool::get_wcs(&config);
+    println!("{:-^30}", " Selecting Weak Classifiers ");
+    let strategies = config.selection_strategies();
+    let (_, report) =
+        feature_selection::select(wcs, &set, &strategies, config.cost_ratio);
+    report.print();
+
+    let data = serde_json::to_string_pretty(&report).unwrap();
+    fs::write("output/selection.json", &data)
+        .expect("Unable to write to file");
 }
 
 /// Tests cached cascade over training images
 fn test() {
     // Get the cached cascade
-    let cascade: Cascade = {
-        if Path::new(CASCADE).exists() {
-            let data = std::fs::read_to_string(CASCADE).unwrap();
-            serde_json::from_str(&data)
-                .expect("Unable to read cached cascade")
-        } else {
-            println!("Cascade not found in cache");
-            return;
-        }
+    let cascade = match load_cascade() {
+        Some(cascade) => cascade,
+        None => return,
     };
 
     // Get processed training images from cache
-    let train_set: Vec<ImageData> = {
-        if Path::new(CACHED_IMAGES).exists() {
-            let data =
-                std::fs::read_to_string(CACHED_IMAGES).unwrap();
-            serde_json::from_str(&data)
-                .expect("Unable to read cached image data")
-        } else {
-            println!("Testing image data not found in cache");
-            return;
-        }
+    let train_set = match load_images() {
+        Some(set) => set,
+        None => return,
     };
 
     println!("Testing the Cascade...");
@@ -117,76 +251,616 @@ fn test() {
     println!("Detection Rate: {}", dtr);
 }
 
-/// This detects objects by sending a "windowed" view into the image
-/// to be evaluated by the cascade. The window moves across the image
-/// and grows in size. This tests all rectangles in the images for the
-/// object
-fn detect(m: &clap::ArgMatches) {
-    // Get the cached cascade
-    let cascade: Cascade = {
-        if Path::new(CASCADE).exists() {
-            let data = std::fs::read_to_string(CASCADE).unwrap();
-            serde_json::from_str(&data).unwrap()
-        } else {
-            println!("Cascade not found in cache");
-            return;
+/// Detects all instances of the object in a batch of images
+fn detect_objects(m: &clap::ArgMatches) {
+    let inputs: Vec<&str> = m.values_of("input_image")
+        .map_or(Vec::new(), |v| v.collect());
+    let paths = batch::input_paths(&inputs, m.value_of("list"));
+    if paths.is_empty() {
+        println!("No images found to detect objects in");
+        return;
+    }
+    let options = BatchOptions {
+        output_dir: m.value_of("output_dir").unwrap().to_owned(),
+        formats: OutputFormat::parse_list(m.value_of("format").unwrap())
+            .unwrap_or_else(|e| panic!("{}", e)),
+        coco_ids: m.value_of("coco_annotations").map(CocoIds::load),
+        style: render_style(m),
+        ground_truth: m.is_present("ground_truth"),
+        score_maps: m.value_of("score_maps")
+            .map(|k| k.parse().unwrap_or_else(|e| panic!("{}", e))),
+        jobs: m.value_of("jobs").map_or(0, |j| {
+            j.parse().expect("Jobs must be an integer")
+        }),
+    };
+
+    // Find the objects in the images with the cached soft cascade or
+    // with every cascade given
+    let label = m.value_of("label").unwrap();
+    let summary = if m.is_present("soft") {
+        let soft = match load_soft_cascade() {
+            Some(soft) => soft,
+            None => return,
+        };
+        let window = soft.window();
+        let model = Model { label: label.to_owned(), classifier: soft, window };
+        batch::run(&[model], &paths, &options)
+    } else {
+        match load_models(m) {
+            Some(models) => batch::run(&models, &paths, &options),
+            None => return,
         }
     };
+    summary.print();
+    println!("Results saved to {}", options.output_dir);
+}
+
+/// Detects and tracks objects over a sequence of images or a stream of
+/// frames from stdin
+fn detect_sequence(m: &clap::ArgMatches) {
+    let input = m.value_of("input").unwrap();
+    let frames = if input == "-" {
+        let format = m.value_of("stdin_format").unwrap().parse()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let size = m.value_of("size").map(|s| {
+            let (w, h) = s.split_once('x').expect("Size must be WxH");
+            [w, h].map(|v| v.parse().expect("Size must be WxH"))
+        });
+        FrameSource::stdin(format, size)
+    } else {
+        FrameSource::images(batch::input_paths(&[input], None))
+    };
 
-    // Get the input image
-    let path = m.value_of("input_image").unwrap();
-
-    // Get the location to store the output image
-    let output_img = "output/".to_owned()
-        + Path::new(path).file_name().unwrap().to_str().unwrap();
-
-    // Open the image
-    let img = DynamicImage::from(path);
-    let img = GreyscaleImage::from(img);
-    let img_width = img.width();
-    let img_height = img.height();
-
-    // Convert image to integral image
-    let ii = IntegralImage::from(&img);
-
-    // Vector to hold detected objects
-    let mut objects = Vec::<Rectangle<u32>>::new();
-
-    let max_width = if (img_width / WL_32) < (img_height / WH_32) {
-        img_width
-    } else { img_height * WL_32 / WH_32 };
-
-    let step_size = (f64::from(WL) / 5.0).round() as usize;
-    for curr_width in (WL_32..=max_width).step_by(step_size) {
-        let curr_height = curr_width * WH_32 / WL_32;
-        let f = f64::from(curr_width) / f64::from(WL_32);
-        for x in 0..(img_width - curr_width) {
-            for y in 0..(img_height - curr_height) {
-                let img = WindowedIntegralImage {
-                    ii: &ii,
-                    x_offset: x as usize,
-                    y_offset: y as usize,
-                    f: f as i64,
-                };
-                if cascade.classify(&img) {
-                    objects.push(Rectangle::<u32>::new(
-                        x, y, curr_width, curr_height
-                    ));
-                }
+    let parse = |name: &str| m.value_of(name).unwrap().parse::<f64>()
+        .unwrap_or_else(|_| panic!("{} must be a number", name));
+    let parse_int = |name: &str| m.value_of(name).unwrap().parse::<usize>()
+        .unwrap_or_else(|_| panic!("{} must be an integer", name));
+    let tracker = Tracker::new(
+        parse("track_iou"), parse_int("max_age"), parse_int("min_hits"),
+    );
+    let options = SequenceOptions {
+        output_dir: m.value_of("output_dir").unwrap().to_owned(),
+        nms: parse("nms"),
+        draw: if m.is_present("draw") { Some(render_style(m)) } else { None },
+        acceleration: m.value_of("full_every").map(|_| Acceleration {
+            full_every: parse_int("full_every"),
+            margin: parse("search_margin"),
+            scale_range: parse("scale_range"),
+        }),
+        compare: m.is_present("compare"),
+    };
+
+    let models = match load_models(m) {
+        Some(models) => models,
+        None => return,
+    };
+    let summary = sequence::run(&models, frames, tracker, &options);
+    summary.print();
+    println!("Results saved to {}", options.output_dir);
+}
+
+/// Serves detection with the given cascades over HTTP
+fn serve(m: &clap::ArgMatches) {
+    let models = match load_models(m) {
+        Some(models) => models,
+        None => return,
+    };
+    let threads = match m.value_of("threads") {
+        Some(t) => t.parse().expect("Threads must be an integer"),
+        None => std::thread::available_parallelism().map_or(1, |n| n.get()),
+    };
+    let options = ServeOptions {
+        address: format!(
+            "{}:{}", m.value_of("host").unwrap(), m.value_of("port").unwrap(),
+        ),
+        threads,
+        nms: m.value_of("nms").map(|v| {
+            v.parse().expect("NMS threshold must be a number")
+        }),
+    };
+    serve::run(&models, &options);
+}
+
+/// Detects objects in the images of json requests read from stdin
+fn worker(m: &clap::ArgMatches) {
+    let models = match load_models(m) {
+        Some(models) => models,
+        None => return,
+    };
+    let nms = m.value_of("nms").map(|v| {
+        v.parse().expect("NMS threshold must be a number")
+    });
+    worker::run(&models, nms);
+}
+
+/// Gets the cascades given with --cascade, each as a path or as
+/// name=path, along with the class of object each finds. The cached
+/// cascade is used if none are given
+fn load_models(m: &clap::ArgMatches) -> Option<Vec<Model<Cascade>>> {
+    let default_label = m.value_of("label").unwrap();
+    let specs: Vec<&str> = m.values_of("cascade")
+        .map_or(vec![CASCADE], |v| v.collect());
+    let mut models = Vec::<Model<Cascade>>::new();
+    for spec in specs {
+        let (name, path) = match spec.split_once('=') {
+            Some((name, path)) => (Some(name), path),
+            None => (None, spec),
+        };
+        let cascade = load_cascade_from(path)?;
+        let label = name.or(cascade.label()).unwrap_or(default_label).to_owned();
+        let window = cascade.window();
+        models.push(Model { label, classifier: cascade, window });
+    }
+    Some(models)
+}
+
+/// Gets how detections are drawn from the cli arguments
+fn render_style(m: &clap::ArgMatches) -> RenderStyle {
+    let mut style = RenderStyle::default();
+    if let Some(colours) = m.value_of("colours") {
+        style.colours = RenderStyle::parse_colours(colours)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+    if let Some(colour) = m.value_of("truth_colour") {
+        style.truth_colour = render::parse_colour(colour)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+    if let Some(t) = m.value_of("thickness") {
+        style.thickness = t.parse().expect("Thickness must be an integer");
+    }
+    if let Some(fill) = m.value_of("fill") {
+        style.fill = Some(fill.parse().expect("Fill must be a number"));
+    }
+    style.labels = !m.is_present("no_labels");
+    style
+}
+
+/// Runs detection over a directory of annotated images and compares
+/// the detections to the annotated objects
+fn evaluate(m: &clap::ArgMatches) {
+    // Get the cached cascade
+    let cascade = match load_cascade() {
+        Some(cascade) => cascade,
+        None => return,
+    };
+
+    let dir = m.value_of("test_dir").unwrap();
+    let min_iou: f64 = m.value_of("iou").unwrap().parse()
+        .expect("IoU must be a number");
+    let max_overlap: f64 = m.value_of("nms").unwrap().parse()
+        .expect("NMS threshold must be a number");
+
+    // Run detection over every annotated image
+    let paths = AnnotatedResult::images_in(dir);
+    let bar = new_bar(paths.len(), "Detecting Objects...");
+    let results: Vec<AnnotatedResult> = paths
+        .par_iter()
+        .map(|path| {
+            let img = ColorImage::from(DynamicImage::from(path.clone()));
+            let ii = IntegralImage::from(&img);
+            let detections = non_max_suppression(
+                detect(&cascade, cascade.window(), &ii), max_overlap,
+            );
+            bar.inc(1);
+            AnnotatedResult {
+                detections,
+                ground_truth: AnnotatedResult::annotations(path),
             }
+        })
+        .collect();
+    bar.finish();
+
+    // Print evaluation results
+    let eval = Evaluation::new(&results, min_iou);
+    println!("{:-^30}", " Evaluation ");
+    println!("Images: {}", eval.num_images);
+    println!("True Positives: {}", eval.true_pos);
+    println!("False Positives: {}", eval.false_pos);
+    println!("False Negatives: {}", eval.false_neg);
+    println!("Precision: {}", eval.precision);
+    println!("Recall: {}", eval.recall);
+    println!("F1 Score: {}", eval.f1);
+    println!("Average Precision: {}", eval.average_precision);
+    println!("False Positives Per Image: {}", eval.false_pos_per_image);
+
+    // Output the evaluation
+    let data = serde_json::to_string_pretty(&eval).unwrap();
+    fs::write("output/evaluation.json", &data)
+        .expect("Unable to write to file");
+}
+
+/// Creates ROC and precision-recall curves of the cached cascade over
+/// the cached training images by sweeping the threshold of the last
+/// strong classifier
+fn curves(m: &clap::ArgMatches) {
+    // Get the cached cascade
+    let cascade = match load_cascade() {
+        Some(cascade) => cascade,
+        None => return,
+    };
+
+    // Get processed training images from cache
+    let set = match load_images() {
+        Some(set) => set,
+        None => return,
+    };
+
+    // Find the numbers of stages to create curves for
+    let stages = if m.is_present("stages") {
+        (1..=cascade.num_stages()).collect()
+    } else { vec![cascade.num_stages()] };
+
+    println!("{:-^30}", " Creating Curves ");
+    let curves: Vec<Curves> = stages
+        .into_iter()
+        .map(|n| {
+            let scores: Vec<(Option<f64>, bool)> = set
+                .par_iter()
+                .map(|d| (cascade.partial_score(&d.image, n), d.is_object))
+                .collect();
+            let c = Curves::new(n, &scores);
+            println!(
+                "Stages: {:<3} ROC AUC: {:<10.6} PR AUC: {:.6}",
+                c.stages, c.roc_auc, c.pr_auc,
+            );
+            c
+        })
+        .collect();
+
+    // Output the curves
+    fs::write("output/curves.csv", Curves::to_csv(&curves))
+        .expect("Unable to write to file");
+    let data = serde_json::to_string_pretty(&curves).unwrap();
+    fs::write("output/curves.json", &data)
+        .expect("Unable to write to file");
+    Curves::plot(&curves).save("output/curves.png").unwrap();
+    println!("Saved curves to output/curves.csv and output/curves.png");
+}
+
+/// Profiles each stage of the cached cascade over either the cached
+/// training images or every window of an image
+fn stats(m: &clap::ArgMatches) {
+    // Get the cached cascade
+    let cascade = match load_cascade() {
+        Some(cascade) => cascade,
+        None => return,
+    };
+
+    let mut profiler = Profiler::new(&cascade);
+    if let Some(path) = m.value_of("image") {
+        // Run the cascade over every window of the image
+        let img = ColorImage::from(DynamicImage::from(path));
+        let ii = IntegralImage::from(&img);
+        for (_, img) in windows(&ii, cascade.window()) {
+            profiler.record(&img, None);
+        }
+    } else {
+        // Run the cascade over the training images
+        let set = match load_images() {
+            Some(set) => set,
+            None => return,
+        };
+        for data in set.iter() {
+            profiler.record(&data.image, Some(data.is_object));
         }
     }
-    println!("Found {} instances of object", objects.len());
 
-    // Reopen the image and conver to rgb, draw rectangles, and then
-    // save image
-    let mut img = ColorImage::from(DynamicImage::from(path));
-    for o in objects.iter_mut() { draw_rectangle(&mut img, o); }
-    // draw_rectangle(&mut img, &objects[0]);
-    img.save(output_img).unwrap();
+    // Print and output the statistics
+    let stats = profiler.finish();
+    println!("{:-^30}", " Cascade Statistics ");
+    stats.print_table();
+    let data = serde_json::to_string_pretty(&stats).unwrap();
+    fs::write("output/stats.json", &data)
+        .expect("Unable to write to file");
+}
 
-    // Output detected object
-    let data = serde_json::to_string_pretty(&objects).unwrap();
-    fs::write("output/object.json", &data)
+/// Estimates how well cascades built with a training config perform
+/// on images they were not trained on
+fn crossval(m: &clap::ArgMatches) {
+    let config = training_config(m);
+    let set = match training_images(&config) {
+        Some(set) => set,
+        None => return,
+    };
+
+    let k: usize = m.value_of("folds").unwrap().parse()
+        .expect("Number of folds must be an integer");
+    let jobs: usize = m.value_of("jobs").map_or(k, |j| {
+        j.parse().expect("Number of jobs must be an integer")
+    });
+    let seed: u64 = m.value_of("seed").unwrap().parse()
+        .expect("Seed must be an integer");
+    if k < 2 {
+        println!("At least two folds are needed");
+        return;
+    }
+
+    println!("{:-^30}", " Cross-Validating ");
+    let folds = CrossValidation::split(set, k, seed);
+    let cv = CrossValidation::run(&folds, &config, jobs);
+
+    // Print cross-validation results
+    println!("{:-^30}", " Cross-Validation ");
+    for (i, fold) in cv.folds.iter().enumerate() {
+        println!(
+            "Fold {}: Stages: {} Detection Rate: {} \
+            False Positive Rate: {} Features Per Window: {}",
+            i + 1, fold.num_stages, fold.detection_rate,
+            fold.false_pos_rate, fold.features_per_window,
+        );
+    }
+    let print = |name: &str, s: &crossval::Summary| {
+        println!("{}: mean {} variance {}", name, s.mean, s.variance);
+    };
+    print("Detection Rate", &cv.detection_rate);
+    print("False Positive Rate", &cv.false_pos_rate);
+    print("Features Per Window", &cv.features_per_window);
+
+    // Output the results
+    let data = serde_json::to_string_pretty(&cv).unwrap();
+    fs::write("output/crossval.json", &data)
+        .expect("Unable to write to file");
+}
+
+/// Trains a cascade for every combination of training settings and
+/// ranks the settings by their performance on validation images
+fn sweep(m: &clap::ArgMatches) {
+    let base = m.value_of("config")
+        .map_or_else(TrainingConfig::default, TrainingConfig::from_file);
+    base.check_layout();
+    let set = match load_images() {
+        Some(set) => set,
+        None => return,
+    };
+
+    // Get the values to try for each setting
+    let ranges = SweepRanges {
+        ws: sweep_values(m, "ws", base.ws),
+        max_false_pos: sweep_values(m, "max_false_pos", base.max_false_pos),
+        min_detection: sweep_values(m, "min_detection", base.min_detection),
+        max_stages: sweep_optional_values(m, "stages", base.max_stages),
+        filter: sweep_optional_values(m, "filter", base.filter),
+        boosting: sweep_values(m, "boosting", base.boosting),
+    };
+    let configs = ranges.configs(&base);
+
+    let validation: f64 = m.value_of("validation").unwrap().parse()
+        .expect("Validation fraction must be a number");
+    let seed: u64 = m.value_of("seed").unwrap().parse()
+        .expect("Seed must be an integer");
+    let target_fpr: f64 = m.value_of("target_fpr").unwrap().parse()
+        .expect("Target false positive rate must be a number");
+
+    println!("{:-^30}", " Sweeping ");
+    println!("Trying {} configurations", configs.len());
+    let (train, valid) = Leaderboard::split(set, validation, seed);
+    let leaderboard = Leaderboard::run(&train, &valid, configs, target_fpr);
+
+    // Print the leaderboard
+    println!("{:-^30}", " Leaderboard ");
+    for e in leaderboard.entries.iter() {
+        println!(
+            "#{} (speed #{}): Detection Rate at {} FPR: {} \
+            Features Per Window: {} Config: {}",
+            e.detection_rank, e.speed_rank, target_fpr,
+            e.detection_rate_at_target, e.features_per_window,
+            serde_json::to_string(&e.config).unwrap(),
+        );
+    }
+
+    // Output the leaderboard
+    fs::write("output/leaderboard.csv", leaderboard.to_csv())
         .expect("Unable to write to file");
+    let data = serde_json::to_string_pretty(&leaderboard).unwrap();
+    fs::write("output/leaderboard.json", &data)
+        .expect("Unable to write to file");
+}
+
+/// Parses the values of a swept setting, or uses the default if the
+/// setting is not swept
+fn sweep_values<T>(m: &clap::ArgMatches, name: &str, default: T) -> Vec<T>
+where
+    T: std::str::FromStr,
+{
+    m.value_of(name).map_or_else(|| vec![default], |v| parse_values(v, name))
+}
+
+/// Parses the values of a swept setting that may be turned off with the
+/// value "none"
+fn sweep_optional_values<T>(
+    m: &clap::ArgMatches,
+    name: &str,
+    default: Option<T>,
+) -> Vec<Option<T>>
+where
+    T: std::str::FromStr,
+{
+    let values = match m.value_of(name) {
+        Some(values) => values,
+        None => return vec![default],
+    };
+    values
+        .split(',')
+        .flat_map(|v| match v.trim() {
+            "none" => vec![None],
+            v => parse_values(v, name).into_iter().map(Some).collect(),
+        })
+        .collect()
+}
+
+/// Parses a comma separated list of values (e.g. 0.3,0.4,0.5). Numeric
+/// values can also be written as a range of the form start:end:step
+/// (e.g. 0.3:0.5:0.1)
+fn parse_values<T: std::str::FromStr>(values: &str, name: &str) -> Vec<T> {
+    let parse = |v: &str| v.trim().parse::<T>().unwrap_or_else(|_| {
+        panic!("Invalid value for {}: {}", name, v)
+    });
+    values
+        .split(',')
+        .flat_map(|v| {
+            let range: Vec<f64> = v
+                .split(':')
+                .map(|n| n.trim().parse().unwrap_or(f64::NAN))
+                .collect();
+            match range[..] {
+                [start, end, step] if step > 0.0 => {
+                    // Round each value to avoid floating point error
+                    let n = ((end - start) / step + 1e-9).floor() as usize;
+                    (0..=n)
+                        .map(|i| {
+                            let v = start + step * i as f64;
+                            parse(&((v * 1e9).round() / 1e9).to_string())
+                        })
+                        .collect()
+                },
+                _ => vec![parse(v)],
+            }
+        })
+        .collect()
+}
+
+/// Gets the training config from a config file and command line
+/// arguments. Arguments override settings in the file
+fn training_config(m: &clap::ArgMatches) -> TrainingConfig {
+    let mut config = m.value_of("config")
+        .map_or_else(TrainingConfig::default, TrainingConfig::from_file);
+
+    let parse = |name: &str| m.value_of(name).map(|v| {
+        v.parse::<f64>().unwrap_or_else(|_| panic!("{} must be a number", name))
+    });
+    if let Some(ws) = m.value_of("ws") {
+        config.ws = ws.parse().expect("ws must be an integer");
+    }
+    if let Some(v) = parse("max_false_pos") { config.max_false_pos = v; }
+    if let Some(v) = parse("min_detection") { config.min_detection = v; }
+    if let Some(v) = parse("target_false_pos") { config.target_false_pos = v; }
+    if let Some(v) = parse("cost_ratio") { config.cost_ratio = v; }
+    if let Some(v) = parse("trim") { config.trim = Some(v); }
+    if let Some(v) = parse("feature_fraction") { config.feature_fraction = Some(v); }
+    if let Some(seed) = m.value_of("sample_seed") {
+        config.sample_seed = seed.parse().expect("sample-seed must be an integer");
+    }
+    if let Some(v) = parse("filter") { config.filter = Some(v); }
+    if let Some(select) = m.value_of("select") {
+        config.selection = select
+            .split(',')
+            .map(|s| s.trim().parse().unwrap_or_else(|e| panic!("{}", e)))
+            .collect();
+    }
+    if let Some(n) = m.value_of("max_stages") {
+        config.max_stages = Some(n.parse().expect("max-stages must be an integer"));
+    }
+    if let Some(boosting) = m.value_of("boosting") {
+        config.boosting = boosting.parse().unwrap();
+    }
+    if let Some(path) = m.value_of("templates") {
+        config.templates = Template::from_file(path);
+    }
+    if let Some(features) = m.value_of("features") {
+        config.features = FeatureFamily::parse_list(features)
+            .unwrap_or_else(|e| panic!("{}", e));
+    }
+    if let Some(d) = m.value_of("tree_depth") {
+        config.tree_depth = Some(d.parse().expect("tree-depth must be an integer"));
+    }
+    if m.is_present("linear_stage") {
+        config.linear_stage = true;
+    }
+    if let Some(label) = m.value_of("label") {
+        config.label = Some(label.to_owned());
+    }
+    if let Some(layout) = m.value_of("layout") {
+        config.layout = Some(layout
+            .split(',')
+            .map(|n| n.trim().parse().expect("Layout must be a list of integers"))
+            .collect());
+    }
+    config.check_layout();
+    config
+}
+
+/// Renders the features of the cached cascade over the mean object
+/// image of the cached training images
+fn visualize(m: &clap::ArgMatches) {
+    let cascade = match load_cascade() {
+        Some(cascade) => cascade,
+        None => return,
+    };
+    let set = match load_images() {
+        Some(set) => set,
+        None => return,
+    };
+    let scale: u32 = m.value_of("scale").unwrap().parse()
+        .expect("Scale must be an integer");
+
+    println!("{:-^30}", " Visualizing Cascade ");
+    let vis = Visualizer::new(&set, cascade.window(), scale);
+    fs::create_dir_all("output/visualize")
+        .expect("Unable to create output directory");
+    vis.background().save("output/visualize/mean.png").unwrap();
+    for (i, sheet) in vis.stages(&cascade).iter().enumerate() {
+        sheet.save(format!("output/visualize/stage_{}.png", i + 1)).unwrap();
+    }
+    vis.heatmap(&cascade).save("output/visualize/heatmap.png").unwrap();
+    println!(
+        "Saved {} contact sheets and a heatmap to output/visualize",
+        cascade.num_stages(),
+    );
+}
+
+/// Gets the training images for a training config. The cached images
+/// are used if they are the size of the window, otherwise the raw
+/// training images are processed again
+fn training_images(config: &TrainingConfig) -> Option<Vec<ImageData>> {
+    let set = load_images()?;
+    let [wl, wh] = config.window();
+    let fits = set.first().is_some_and(|d| {
+        d.image.width() == usize::from(wl)
+            && d.image.height() == usize::from(wh)
+    });
+    if fits { return Some(set) }
+
+    println!("Cached images do not match the window, processing images");
+    Some(ImageData::from_dirs(
+        OBJECT_DIR, OTHER_DIR, SLICE_DIR, NUM_NEG, config.window(),
+    ))
+}
+
+/// Gets the cached cascade
+fn load_cascade() -> Option<Cascade> { load_cascade_from(CASCADE) }
+
+/// Gets a cascade saved to a path
+fn load_cascade_from(path: &str) -> Option<Cascade> {
+    if Path::new(path).exists() {
+        let data = std::fs::read_to_string(path).unwrap();
+        Some(serde_json::from_str(&data)
+            .expect("Unable to read cascade"))
+    } else {
+        println!("Cascade not found at {}", path);
+        None
+    }
+}
+
+/// Gets the cached soft cascade
+fn load_soft_cascade() -> Option<SoftCascade> {
+    if Path::new(SOFT_CASCADE).exists() {
+        let data = std::fs::read_to_string(SOFT_CASCADE).unwrap();
+        Some(serde_json::from_str(&data)
+            .expect("Unable to read cached soft cascade"))
+    } else {
+        println!("Soft cascade not found in cache");
+        None
+    }
+}
+
+/// Gets the processed training images from cache
+fn load_images() -> Option<Vec<ImageData>> {
+    if Path::new(CACHED_IMAGES).exists() {
+        let data = std::fs::read_to_string(CACHED_IMAGES).unwrap();
+        Some(serde_json::from_str(&data)
+            .expect("Unable to read cached image data"))
+    } else {
+        println!("Training image data not found in cache");
+        None
+    }
 }
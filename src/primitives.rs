@@ -22,28 +22,84 @@ impl<T: Unsigned + Copy> Rectangle<T> {
         }
     }
 }
+impl Rectangle<u32> {
+    pub fn width(&self) -> u32 { self.bot_right[0] - self.top_left[0] }
+    pub fn height(&self) -> u32 { self.bot_right[1] - self.top_left[1] }
+    pub fn area(&self) -> u64 {
+        u64::from(self.width()) * u64::from(self.height())
+    }
+
+    /// Calculates the intersection over union of two rectangles
+    pub fn iou(&self, other: &Rectangle<u32>) -> f64 {
+        let xtl = u32::max(self.top_left[0], other.top_left[0]);
+        let ytl = u32::max(self.top_left[1], other.top_left[1]);
+        let xbr = u32::min(self.bot_right[0], other.bot_right[0]);
+        let ybr = u32::min(self.bot_right[1], other.bot_right[1]);
+        if xbr <= xtl || ybr <= ytl { return 0.0 }
+
+        let intersection = u64::from(xbr - xtl) * u64::from(ybr - ytl);
+        let union = self.area() + other.area() - intersection;
+        intersection as f64 / union as f64
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
 pub struct Feature {
     pub black: (Window, Option<Window>),
     pub white: (Window, Option<Window>),
+
+    /// The channel the rectangles are summed over (see channel_stack()),
+    /// or None to sum the pixels of the greyscale image
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u8>,
 }
 impl Feature {
+    /// Makes a channel feature: the sum of a channel over a single
+    /// black rectangle. The white rectangle is empty
+    pub fn channel_sum(channel: u8, rect: Window) -> Feature {
+        let [x, y] = rect.top_left;
+        Feature {
+            black: (rect, None),
+            white: (Window::new(x, y, 0, 0), None),
+            channel: Some(channel),
+        }
+    }
+
+    /// Gets the non-empty rectangles of the feature, paired with
+    /// whether they are black
+    pub fn rectangles(&self) -> Vec<(Window, bool)> {
+        let [(b1, b2), (w1, w2)] = [self.black, self.white];
+        [(Some(b1), true), (b2, true), (Some(w1), false), (w2, false)]
+            .iter()
+            .filter_map(|&(r, black)| r.map(|r| (r, black)))
+            .filter(|(r, _)| r.top_left != r.bot_right)
+            .collect()
+    }
+
     /// Evaluates a feature over a window of an integral image
     pub fn evaluate(&self, img: &impl IntegralImageTrait) -> i64 {
-        img.rect_sum(&self.black.0)
-            + self.black.1.map_or(0, |r| img.rect_sum(&r))
-            - img.rect_sum(&self.white.0)
-            - self.white.1.map_or(0, |r| img.rect_sum(&r))
+        let sum = |r: &Window| match self.channel {
+            Some(c) => img.channel_sum(usize::from(c), r),
+            None => img.rect_sum(r),
+        };
+        sum(&self.black.0)
+            + self.black.1.map_or(0, |r| sum(&r))
+            - sum(&self.white.0)
+            - self.white.1.map_or(0, |r| sum(&r))
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub struct OrderedF64(pub f64);
 impl Eq for OrderedF64 {}
+impl PartialOrd for OrderedF64 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 impl Ord for OrderedF64 {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+        self.0.partial_cmp(&other.0).unwrap_or(Ordering::Equal)
     }
 }
 
@@ -0,0 +1,312 @@
+use image::Rgb;
+
+use super::{ColorImage, LabeledDetection, Rectangle, TrackedDetection};
+
+/// The colours given to classes without a colour of their own, in the
+/// order of the classes
+const PALETTE: [[u8; 3]; 8] = [
+    [0xE6, 0x19, 0x4B],
+    [0x3C, 0xB4, 0x4B],
+    [0x43, 0x63, 0xD8],
+    [0xF5, 0x82, 0x31],
+    [0x91, 0x1E, 0xB4],
+    [0x42, 0xD4, 0xF4],
+    [0xF0, 0x32, 0xE6],
+    [0xFF, 0xE1, 0x19],
+];
+
+/// The width and height in pixels of a character of the bitmap font
+const GLYPH_SIZE: [u32; 2] = [5, 7];
+
+/// The number of pixels around the text of a label
+const LABEL_PADDING: u32 = 1;
+
+/// How detections and annotated objects are drawn over an image
+#[derive(Debug, Clone)]
+pub struct RenderStyle {
+    /// The colours of classes. Other classes are given a colour from
+    /// the palette
+    pub colours: Vec<(String, Rgb<u8>)>,
+
+    /// The colour of annotated objects
+    pub truth_colour: Rgb<u8>,
+
+    /// The width in pixels of the outlines of boxes
+    pub thickness: u32,
+
+    /// Whether or not the class and score of each detection is written
+    /// above its box
+    pub labels: bool,
+
+    /// The opacity boxes are filled with, if they are filled
+    pub fill: Option<f64>,
+} impl Default for RenderStyle {
+    fn default() -> Self {
+        RenderStyle {
+            colours: Vec::new(),
+            truth_colour: Rgb([0x00, 0xFF, 0x00]),
+            thickness: 2,
+            labels: true,
+            fill: None,
+        }
+    }
+} impl RenderStyle {
+    /// Parses a comma separated list of class colours such as
+    /// "face=ff0000,car=00ff00"
+    pub fn parse_colours(s: &str) -> Result<Vec<(String, Rgb<u8>)>, String> {
+        s.split(',')
+            .map(|pair| match pair.split_once('=') {
+                Some((label, colour)) => {
+                    Ok((label.trim().to_owned(), parse_colour(colour)?))
+                },
+                None => Err(format!("Expected label=colour, got {}", pair)),
+            })
+            .collect()
+    }
+
+    /// Gets the colour of a class. The class is given the colour of
+    /// its position in the list of classes if it has no colour set
+    pub fn colour(&self, label: &str, labels: &[String]) -> Rgb<u8> {
+        if let Some((_, colour)) = self.colours.iter().find(|(l, _)| l == label) {
+            return *colour;
+        }
+        let i = labels.iter().position(|l| l == label).unwrap_or(labels.len());
+        Rgb(PALETTE[i % PALETTE.len()])
+    }
+
+    /// Draws annotated objects and then detections over an image
+    pub fn render(
+        &self,
+        img: &mut ColorImage,
+        detections: &[LabeledDetection],
+        labels: &[String],
+        ground_truth: &[Rectangle<u32>],
+    ) {
+        for r in ground_truth.iter() {
+            self.draw(img, r, self.truth_colour);
+        }
+        for d in detections.iter() {
+            let colour = self.colour(&d.label, labels);
+            self.draw(img, &d.detection.rect, colour);
+        }
+        if !self.labels { return }
+
+        // Labels are drawn last so that boxes do not cover them
+        for d in detections.iter() {
+            let colour = self.colour(&d.label, labels);
+            let text = format!("{} {:.2}", d.label, d.detection.score);
+            draw_label(img, &d.detection.rect, &text, colour);
+        }
+    }
+
+    /// Draws detections that belong to tracks over an image. Each
+    /// track is given a colour from the palette by its id unless its
+    /// class has a colour set, and its id is written in its label
+    pub fn render_tracks(&self, img: &mut ColorImage, tracked: &[TrackedDetection]) {
+        let colour = |t: &TrackedDetection| {
+            self.colours
+                .iter()
+                .find(|(l, _)| *l == t.labeled.label)
+                .map_or(Rgb(PALETTE[t.track as usize % PALETTE.len()]), |c| c.1)
+        };
+        for t in tracked.iter() {
+            self.draw(img, &t.labeled.detection.rect, colour(t));
+        }
+        if !self.labels { return }
+        for t in tracked.iter() {
+            let d = &t.labeled;
+            let text = format!(
+                "{} #{} {:.2}", d.label, t.track, d.detection.score,
+            );
+            draw_label(img, &d.detection.rect, &text, colour(t));
+        }
+    }
+
+    /// Draws a box with the style's outline and fill
+    fn draw(&self, img: &mut ColorImage, r: &Rectangle<u32>, colour: Rgb<u8>) {
+        if let Some(alpha) = self.fill {
+            fill_box(img, r, colour, alpha);
+        }
+        draw_box(img, r, colour, self.thickness);
+    }
+}
+
+/// Parses a colour written in hex as RRGGBB (with or without a '#')
+pub fn parse_colour(s: &str) -> Result<Rgb<u8>, String> {
+    let hex = s.trim().trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok())
+    };
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok(Rgb([r, g, b])),
+        _ => Err(format!("Invalid colour: {}", s)),
+    }
+}
+
+/// Sets a pixel of an image, ignoring pixels outside of the image
+fn put(img: &mut ColorImage, x: u32, y: u32, pixel: Rgb<u8>) {
+    if x < img.width() && y < img.height() { img.put_pixel(x, y, pixel); }
+}
+
+/// Draws the outline of a rectangle over an image. The outline is
+/// drawn inside the rectangle, and the parts of it outside of the
+/// image are ignored
+pub fn draw_box(
+    img: &mut ColorImage,
+    r: &Rectangle<u32>,
+    colour: Rgb<u8>,
+    thickness: u32,
+) {
+    let [x1, y1] = r.top_left;
+    let [x2, y2] = r.bot_right;
+    for i in 0..thickness.max(1) {
+        if x1 + 2 * i >= x2 || y1 + 2 * i >= y2 { break }
+        let [left, top] = [x1 + i, y1 + i];
+        let [right, bottom] = [x2 - i - 1, y2 - i - 1];
+        for x in left..=right {
+            put(img, x, top, colour);
+            put(img, x, bottom, colour);
+        }
+        for y in top..=bottom {
+            put(img, left, y, colour);
+            put(img, right, y, colour);
+        }
+    }
+}
+
+/// Blends a colour over the pixels of an image inside a rectangle
+pub fn fill_box(
+    img: &mut ColorImage,
+    r: &Rectangle<u32>,
+    colour: Rgb<u8>,
+    alpha: f64,
+) {
+    let alpha = alpha.clamp(0.0, 1.0);
+    let x2 = r.bot_right[0].min(img.width());
+    let y2 = r.bot_right[1].min(img.height());
+    for y in r.top_left[1]..y2 {
+        for x in r.top_left[0]..x2 {
+            let pixel = img.get_pixel_mut(x, y);
+            for c in 0..3 {
+                pixel[c] = (f64::from(pixel[c]) * (1.0 - alpha)
+                    + f64::from(colour[c]) * alpha) as u8;
+            }
+        }
+    }
+}
+
+/// Writes text in a colour with its top left corner at a position.
+/// Each character is a glyph of the built in bitmap font scaled up by
+/// a whole number
+pub fn draw_text(
+    img: &mut ColorImage,
+    x: u32,
+    y: u32,
+    text: &str,
+    colour: Rgb<u8>,
+    scale: u32,
+) {
+    let gw = GLYPH_SIZE[0];
+    for (i, c) in text.chars().enumerate() {
+        let left = x + i as u32 * (gw + 1) * scale;
+        for (row, bits) in glyph(c).iter().enumerate() {
+            for col in 0..gw {
+                if bits & (1 << (gw - 1 - col)) == 0 { continue }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = left + col * scale + dx;
+                        let py = y + (row as u32) * scale + dy;
+                        put(img, px, py, colour);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Gets the width and height in pixels of text written at a scale
+pub fn text_size(text: &str, scale: u32) -> [u32; 2] {
+    let [gw, gh] = GLYPH_SIZE;
+    let len = text.chars().count() as u32;
+    [(len * (gw + 1)).saturating_sub(1) * scale, gh * scale]
+}
+
+/// Writes a label on a background of the colour of its box, above the
+/// box or inside its top if there is no room above it, and moved left
+/// if it would run past the right of the image. The text is black or
+/// white, whichever stands out more from the colour
+fn draw_label(
+    img: &mut ColorImage,
+    r: &Rectangle<u32>,
+    text: &str,
+    colour: Rgb<u8>,
+) {
+    let scale = (img.width().min(img.height()) / 400).max(1);
+    let [w, h] = text_size(text, scale);
+    let [bw, bh] = [w + 2 * LABEL_PADDING, h + 2 * LABEL_PADDING];
+    let x = r.top_left[0].min(img.width().saturating_sub(bw));
+    let y = r.top_left[1].checked_sub(bh).unwrap_or(r.top_left[1]);
+    fill_box(img, &Rectangle::new(x, y, bw, bh), colour, 1.0);
+
+    let [red, green, blue] = colour.0.map(f64::from);
+    let luma = 0.299 * red + 0.587 * green + 0.114 * blue;
+    let text_colour = if luma > 140.0 {
+        Rgb([0x00, 0x00, 0x00])
+    } else { Rgb([0xFF, 0xFF, 0xFF]) };
+    let (tx, ty) = (x + LABEL_PADDING, y + LABEL_PADDING);
+    draw_text(img, tx, ty, text, text_colour, scale);
+}
+
+/// Gets the rows of the glyph of a character in the bitmap font, with
+/// the leftmost column of each row in its highest bit. Lowercase
+/// letters use the glyphs of uppercase letters, and characters without
+/// a glyph are drawn as '?'
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        '/' => [0x00, 0x01, 0x02, 0x04, 0x08, 0x10, 0x00],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
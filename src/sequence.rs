@@ -0,0 +1,573 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Instant;
+
+use image::Rgb;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    detect, detect_in, non_max_suppression, windows, Classifier, ColorImage,
+    DynamicImage, FailedImage, IntegralImage, IntegralImageTrait,
+    LabeledDetection, Model, Prediction, Rectangle, RenderStyle,
+    SearchRegion, TrackedDetection, Tracker,
+};
+
+/// The minimum intersection over union of a detection of a full search
+/// and one of an accelerated search for the object to count as found
+const COMPARISON_IOU: f64 = 0.5;
+
+/// The formats of frame streams read from stdin
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum StreamFormat {
+    /// A YUV4MPEG2 stream with 8 bit samples
+    Y4m,
+
+    /// Raw 8 bit greyscale frames of a size given separately
+    Grey,
+} impl FromStr for StreamFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "y4m" => Ok(StreamFormat::Y4m),
+            "grey" => Ok(StreamFormat::Grey),
+            _ => Err(format!("Unknown stream format: {}", s)),
+        }
+    }
+}
+
+/// How the colour of the pixels of a Y4M stream is sampled
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Chroma {
+    /// U and V at half the width and height
+    C420,
+
+    /// U and V at half the width
+    C422,
+
+    /// U and V at every pixel
+    C444,
+
+    /// Only Y
+    Mono,
+} impl Chroma {
+    /// Parses the colour space parameter of a Y4M header
+    fn parse(tag: &str) -> Chroma {
+        match tag {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => Chroma::C420,
+            "422" => Chroma::C422,
+            "444" => Chroma::C444,
+            "mono" => Chroma::Mono,
+            _ => panic!("Unsupported Y4M colour space: {}", tag),
+        }
+    }
+
+    /// Gets the width and height of the U and V planes of a frame
+    fn plane_size(&self, width: usize, height: usize) -> (usize, usize) {
+        match self {
+            Chroma::C420 => (width.div_ceil(2), height.div_ceil(2)),
+            Chroma::C422 => (width.div_ceil(2), height),
+            Chroma::C444 => (width, height),
+            Chroma::Mono => (0, 0),
+        }
+    }
+}
+
+/// A frame of a sequence
+pub struct Frame {
+    /// The path of the image the frame was read from, if it was read
+    /// from an image
+    pub image: Option<String>,
+    pub pixels: ColorImage,
+}
+
+/// Where the frames of a sequence are read from, in order
+pub enum FrameSource {
+    /// Numbered images
+    Images(std::vec::IntoIter<PathBuf>),
+
+    /// A YUV4MPEG2 stream
+    Y4m {
+        reader: io::StdinLock<'static>,
+        width: usize,
+        height: usize,
+        chroma: Chroma,
+    },
+
+    /// Raw 8 bit greyscale frames
+    Grey {
+        reader: io::StdinLock<'static>,
+        width: usize,
+        height: usize,
+    },
+} impl FrameSource {
+    /// Reads a sequence of images ordered by the number in their names
+    pub fn images(mut paths: Vec<PathBuf>) -> FrameSource {
+        paths.sort_by_key(|p| (frame_number(p), p.clone()));
+        FrameSource::Images(paths.into_iter())
+    }
+
+    /// Reads frames from stdin. The size of frames is read from the
+    /// header of Y4M streams and must be given for greyscale streams
+    pub fn stdin(format: StreamFormat, size: Option<[usize; 2]>) -> FrameSource {
+        let mut reader = io::stdin().lock();
+        match format {
+            StreamFormat::Y4m => {
+                let mut header = String::new();
+                reader.read_line(&mut header).expect("Unable to read stream");
+                let mut params = header.split_whitespace();
+                if params.next() != Some("YUV4MPEG2") {
+                    panic!("Stream is not a YUV4MPEG2 stream");
+                }
+                let (mut width, mut height) = (0, 0);
+                let mut chroma = Chroma::C420;
+                for param in params {
+                    let (tag, value) = param.split_at(1);
+                    match tag {
+                        "W" => width = value.parse().expect("Invalid width"),
+                        "H" => height = value.parse().expect("Invalid height"),
+                        "C" => chroma = Chroma::parse(value),
+                        _ => {},
+                    }
+                }
+                FrameSource::Y4m { reader, width, height, chroma }
+            },
+            StreamFormat::Grey => {
+                let [width, height] = size
+                    .expect("The size of greyscale frames must be given");
+                FrameSource::Grey { reader, width, height }
+            },
+        }
+    }
+} impl Iterator for FrameSource {
+    /// A frame, or the image of a frame that could not be read
+    type Item = Result<Frame, FailedImage>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            FrameSource::Images(paths) => {
+                let path = paths.next()?;
+                let image = path.display().to_string();
+                let decoded = fs::read(&path)
+                    .map_err(|e| format!("Unable to read: {}", e))
+                    .and_then(|bytes| DynamicImage::from_bytes(&bytes));
+                Some(match decoded {
+                    Ok(img) => Ok(Frame {
+                        image: Some(image),
+                        pixels: ColorImage::from(img),
+                    }),
+                    Err(error) => Err(FailedImage { image, error }),
+                })
+            },
+            FrameSource::Y4m { reader, width, height, chroma } => {
+                let mut line = String::new();
+                if reader.read_line(&mut line).ok()? == 0 { return None }
+                if !line.starts_with("FRAME") {
+                    panic!("Expected a Y4M frame header");
+                }
+                let (w, h) = (*width, *height);
+                let (cw, ch) = chroma.plane_size(w, h);
+                let luma = read_plane(reader, w * h)?;
+                let u = read_plane(reader, cw * ch)?;
+                let v = read_plane(reader, cw * ch)?;
+                let pixels = ColorImage::from_fn(w as u32, h as u32, |x, y| {
+                    let (x, y) = (x as usize, y as usize);
+                    let l = luma[x + w * y];
+                    if *chroma == Chroma::Mono { return Rgb([l; 3]) }
+                    let i = (x * cw / w) + cw * (y * ch / h);
+                    yuv_to_rgb(l, u[i], v[i])
+                });
+                Some(Ok(Frame { image: None, pixels }))
+            },
+            FrameSource::Grey { reader, width, height } => {
+                let (w, h) = (*width, *height);
+                let grey = read_plane(reader, w * h)?;
+                let pixels = ColorImage::from_fn(w as u32, h as u32, |x, y| {
+                    Rgb([grey[x as usize + w * y as usize]; 3])
+                });
+                Some(Ok(Frame { image: None, pixels }))
+            },
+        }
+    }
+}
+
+/// Reads a plane of samples, or None if the stream has ended
+fn read_plane(reader: &mut impl Read, len: usize) -> Option<Vec<u8>> {
+    let mut plane = vec![0; len];
+    match reader.read_exact(&mut plane) {
+        Ok(()) => Some(plane),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+        Err(e) => panic!("Unable to read frame: {}", e),
+    }
+}
+
+/// Converts a pixel from Y'CbCr with the BT.601 studio range to RGB
+fn yuv_to_rgb(y: u8, u: u8, v: u8) -> Rgb<u8> {
+    let y = 1.164 * (f64::from(y) - 16.0);
+    let (u, v) = (f64::from(u) - 128.0, f64::from(v) - 128.0);
+    let c = |value: f64| value.round().clamp(0.0, 255.0) as u8;
+    Rgb([c(y + 1.596 * v), c(y - 0.392 * u - 0.813 * v), c(y + 2.017 * u)])
+}
+
+/// Gets the last number in the name of a file, the number of the frame
+/// in a sequence of images. Files without a number come first
+fn frame_number(path: &Path) -> Option<u64> {
+    let stem = path.file_stem()?.to_str()?;
+    stem.split(|c: char| !c.is_ascii_digit())
+        .rfind(|s| !s.is_empty())
+        .and_then(|s| s.parse().ok())
+}
+
+/// Settings for running detection over a sequence of frames
+pub struct SequenceOptions {
+    /// The directory the results are written to
+    pub output_dir: String,
+
+    /// The maximum intersection over union of two detections of the
+    /// same class in a frame
+    pub nms: f64,
+
+    /// How detections are drawn over frames, if frames are saved
+    pub draw: Option<RenderStyle>,
+
+    /// How the frames between full searches are searched, if only
+    /// some frames are searched in full
+    pub acceleration: Option<Acceleration>,
+
+    /// Whether or not every frame is also searched in full to compare
+    /// the speed and recall of accelerated searches with
+    pub compare: bool,
+}
+
+/// Settings for searching only some frames in full. The frames between
+/// are only searched around where the objects being tracked are
+/// expected, with windows of about their size
+#[derive(Debug, Copy, Clone)]
+pub struct Acceleration {
+    /// The number of frames from one full search to the next
+    pub full_every: usize,
+
+    /// How far the region searched around a track extends past where
+    /// it is expected on each side, as a fraction of its larger side
+    pub margin: f64,
+
+    /// The largest factor the width of a window searched around a
+    /// track may differ from the expected width of the track by
+    pub scale_range: f64,
+} impl Acceleration {
+    /// Gets whether or not a frame is searched in full
+    fn is_full(&self, frame: usize) -> bool {
+        frame.is_multiple_of(self.full_every.max(1))
+    }
+
+    /// Gets the regions searched around the tracks of a class. Regions
+    /// are cut off at the edges of the image, and tracks expected too
+    /// far outside of it are not searched around
+    fn regions(
+        &self,
+        predictions: &[Prediction],
+        label: &str,
+        ii: &IntegralImage,
+    ) -> Vec<SearchRegion> {
+        let (img_width, img_height) = (ii.width() as u32, ii.height() as u32);
+        predictions
+            .iter()
+            .filter(|(l, _)| l == label)
+            .filter_map(|(_, r)| {
+                let side = f64::from(r.width().max(r.height()));
+                let margin = (self.margin * side).ceil() as u32;
+                let [x1, y1] = r.top_left.map(|v| v.saturating_sub(margin));
+                let x2 = r.bot_right[0].saturating_add(margin).min(img_width);
+                let y2 = r.bot_right[1].saturating_add(margin).min(img_height);
+                if x1 >= x2 || y1 >= y2 { return None }
+                let width = f64::from(r.width());
+                let range = self.scale_range.max(1.0);
+                Some(SearchRegion {
+                    rect: Rectangle::new(x1, y1, x2 - x1, y2 - y1),
+                    min_width: (width / range).floor() as u32,
+                    max_width: (width * range).ceil() as u32,
+                })
+            })
+            .collect()
+    }
+}
+
+/// The detections of one frame of a sequence
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FrameDetections {
+    pub frame: usize,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+
+    pub width: u32,
+    pub height: u32,
+    pub detections: Vec<TrackedDetection>,
+}
+
+/// A summary of detection over a sequence of frames
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SequenceSummary {
+    pub num_frames: usize,
+    pub num_detections: usize,
+    pub num_tracks: u64,
+
+    /// The number of frames that were searched in full
+    pub full_searches: usize,
+    pub windows_per_frame: f64,
+    pub seconds: f64,
+    pub frames_per_second: f64,
+
+    /// How the searches compare to searching every frame in full, if
+    /// they were compared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comparison: Option<Comparison>,
+
+    /// The images of frames that could not be read, which were skipped
+    #[serde(default)]
+    pub failed_frames: Vec<FailedImage>,
+} impl SequenceSummary {
+    pub fn print(&self) {
+        println!("{:-^30}", " Sequence Summary ");
+        println!("Frames: {}", self.num_frames);
+        println!("Full Searches: {}", self.full_searches);
+        println!("Detections: {}", self.num_detections);
+        println!("Tracks: {}", self.num_tracks);
+        println!("Windows Per Frame: {:.0}", self.windows_per_frame);
+        println!(
+            "Time: {:.2}s ({:.2} frames per second)",
+            self.seconds, self.frames_per_second,
+        );
+        if let Some(ref c) = self.comparison {
+            println!("{:-^30}", " Compared To Full Searches ");
+            println!("Full Windows Per Frame: {:.0}", c.full_windows_per_frame);
+            println!(
+                "Detection Time: {:.3}s vs {:.3}s ({:.2}x faster)",
+                c.seconds, c.full_seconds, c.speedup,
+            );
+            println!(
+                "Recall: {:.3} ({} of {} detections)",
+                c.recall, c.found, c.full_detections,
+            );
+        }
+        if !self.failed_frames.is_empty() {
+            println!("Failed Frames: {}", self.failed_frames.len());
+            for failed in self.failed_frames.iter() {
+                println!("  {}: {}", failed.image, failed.error);
+            }
+        }
+    }
+}
+
+/// The speed and recall of searching frames as configured compared to
+/// searching every frame in full. Recall is the fraction of the
+/// detections of full searches (after suppressing overlaps) that
+/// overlap a detection of the same class found as configured
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct Comparison {
+    pub full_windows_per_frame: f64,
+
+    /// The time spent detecting objects as configured
+    pub seconds: f64,
+
+    /// The time spent detecting objects with full searches
+    pub full_seconds: f64,
+    pub speedup: f64,
+    pub full_detections: usize,
+    pub found: usize,
+    pub recall: f64,
+}
+
+/// Detects objects with every model in each frame of a sequence and
+/// follows them from frame to frame. The detections of each frame are
+/// written to tracks.jsonl as they are found, and the frames with
+/// their detections drawn over them are saved to frames/ if requested.
+/// Frames that cannot be read are skipped and listed in the summary
+pub fn run(
+    models: &[Model<impl Classifier>],
+    frames: FrameSource,
+    mut tracker: Tracker,
+    options: &SequenceOptions,
+) -> SequenceSummary {
+    let start = Instant::now();
+    let dir = Path::new(&options.output_dir);
+    let frames_dir = if options.draw.is_some() {
+        dir.join("frames")
+    } else { dir.to_owned() };
+    fs::create_dir_all(&frames_dir)
+        .expect("Unable to create output directory");
+    let file = File::create(dir.join("tracks.jsonl"))
+        .expect("Unable to write to file");
+    let mut out = BufWriter::new(file);
+
+    let (mut num_frames, mut num_detections) = (0, 0);
+    let (mut full_searches, mut num_windows) = (0, 0);
+    let mut comparison = Comparison::default();
+    let mut full_windows = 0;
+    let mut failed_frames = Vec::<FailedImage>::new();
+    for frame in frames {
+        let frame = match frame {
+            Ok(frame) => frame,
+            Err(failed) => {
+                println!("Skipping {}: {}", failed.image, failed.error);
+                failed_frames.push(failed);
+                continue;
+            },
+        };
+        let i = num_frames;
+        let ii = IntegralImage::from(&frame.pixels);
+
+        // Search around the tracks unless the frame is searched in full
+        let predictions = tracker.predictions();
+        let search = options.acceleration
+            .filter(|a| !a.is_full(i))
+            .map(|a| (a, predictions.as_slice()));
+        let detect_start = Instant::now();
+        let (detections, tested) = detect_frame(models, &ii, options.nms, search);
+        let detect_seconds = detect_start.elapsed().as_secs_f64();
+        if search.is_none() { full_searches += 1 }
+        num_windows += tested;
+
+        // Search the frame in full as well to compare the results
+        if options.compare {
+            comparison.seconds += detect_seconds;
+            let full = if search.is_some() {
+                let full_start = Instant::now();
+                let full = detect_frame(models, &ii, options.nms, None);
+                comparison.full_seconds += full_start.elapsed().as_secs_f64();
+                full
+            } else {
+                comparison.full_seconds += detect_seconds;
+                (detections.clone(), tested)
+            };
+            full_windows += full.1;
+            comparison.full_detections += full.0.len();
+            comparison.found += count_found(&full.0, &detections);
+        }
+        let tracked = tracker.update(&detections);
+
+        if let Some(ref style) = options.draw {
+            let mut img = frame.pixels.clone();
+            style.render_tracks(&mut img, &tracked);
+            img.save(frames_dir.join(format!("{:06}.png", i))).unwrap();
+        }
+
+        num_frames += 1;
+        num_detections += tracked.len();
+        let result = FrameDetections {
+            frame: i,
+            image: frame.image,
+            width: frame.pixels.width(),
+            height: frame.pixels.height(),
+            detections: tracked,
+        };
+        let line = serde_json::to_string(&result).unwrap();
+        writeln!(out, "{}", line).expect("Unable to write to file");
+    }
+    out.flush().expect("Unable to write to file");
+
+    let seconds = start.elapsed().as_secs_f64();
+    let per_frame = |n: usize| n as f64 / num_frames.max(1) as f64;
+    let comparison = if options.compare {
+        Some(Comparison {
+            full_windows_per_frame: per_frame(full_windows),
+            speedup: comparison.full_seconds
+                / comparison.seconds.max(f64::EPSILON),
+            recall: comparison.found as f64
+                / comparison.full_detections.max(1) as f64,
+            ..comparison
+        })
+    } else { None };
+    let summary = SequenceSummary {
+        num_frames,
+        num_detections,
+        num_tracks: tracker.num_tracks(),
+        full_searches,
+        windows_per_frame: per_frame(num_windows),
+        seconds,
+        frames_per_second: num_frames as f64 / seconds.max(f64::EPSILON),
+        comparison,
+        failed_frames,
+    };
+    let data = serde_json::to_string_pretty(&summary).unwrap();
+    fs::write(dir.join("summary.json"), data).expect("Unable to write to file");
+    summary
+}
+
+/// Detects objects with every model in a frame, either in full or
+/// only around where tracks are expected. Overlapping detections of
+/// each model are suppressed so that each object is tracked once.
+/// Returns the detections and the number of windows tested
+fn detect_frame(
+    models: &[Model<impl Classifier>],
+    ii: &IntegralImage,
+    nms: f64,
+    search: Option<(Acceleration, &[Prediction])>,
+) -> (Vec<LabeledDetection>, usize) {
+    let mut detections = Vec::<LabeledDetection>::new();
+    let mut num_windows = 0;
+    for model in models {
+        let (found, tested) = match search {
+            Some((acceleration, predictions)) => {
+                let regions = acceleration.regions(predictions, &model.label, ii);
+                detect_in(&model.classifier, model.window, ii, &regions)
+            },
+            None => (
+                detect(&model.classifier, model.window, ii),
+                windows(ii, model.window).count(),
+            ),
+        };
+        num_windows += tested;
+        detections.extend(model.labeled(non_max_suppression(found, nms)));
+    }
+    (detections, num_windows)
+}
+
+/// Counts the detections of a full search that were found again by
+/// another search. Each detection found can only match one
+fn count_found(full: &[LabeledDetection], found: &[LabeledDetection]) -> usize {
+    let mut matched = vec![false; found.len()];
+    full.iter()
+        .filter(|d| {
+            let i = found.iter().zip(matched.iter()).position(|(f, m)| {
+                !m && f.label == d.label
+                    && f.detection.rect.iou(&d.detection.rect) >= COMPARISON_IOU
+            });
+            if let Some(i) = i { matched[i] = true }
+            i.is_some()
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::GreyscaleImage;
+
+    #[test]
+    fn regions_stay_inside_the_frame() {
+        let ii = IntegralImage::from(&GreyscaleImage::new(40, 30));
+        let acceleration = Acceleration {
+            full_every: 5,
+            margin: 0.5,
+            scale_range: 1.5,
+        };
+        let track = |x, y| ("object".to_owned(), Rectangle::new(x, y, 10, 8));
+        let predictions = [
+            track(2, 3),
+            track(35, 25),
+            track(46, 10),
+            track(10, 40),
+            ("other".to_owned(), Rectangle::new(0, 0, 10, 8)),
+        ];
+        let regions = acceleration.regions(&predictions, "object", &ii);
+
+        // Tracks that left the frame past the margin are not searched
+        let rects: Vec<_> = regions
+            .iter()
+            .map(|r| (r.rect.top_left, r.rect.bot_right))
+            .collect();
+        assert_eq!(rects, [([0, 0], [17, 16]), ([30, 20], [40, 30])]);
+        assert_eq!((regions[0].min_width, regions[0].max_width), (6, 15));
+    }
+}
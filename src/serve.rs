@@ -0,0 +1,181 @@
+use std::io::Read;
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use super::{
+    detect, non_max_suppression, Cascade, ColorImage, DynamicImage,
+    IntegralImage, LabeledDetection, Model,
+};
+
+/// The largest image accepted by the detect endpoint, in bytes
+const MAX_UPLOAD: usize = 64 * 1024 * 1024;
+
+/// Settings for serving detection over HTTP
+pub struct ServeOptions {
+    /// The address the server listens on, such as 127.0.0.1:8080
+    pub address: String,
+
+    /// The number of requests handled at once
+    pub threads: usize,
+
+    /// The maximum intersection over union of two detections of the
+    /// same class, if overlapping detections are suppressed by default
+    pub nms: Option<f64>,
+}
+
+/// Information about a model being served
+#[derive(Serialize)]
+struct ModelInfo<'a> {
+    label: &'a str,
+    window: [u32; 2],
+    num_stages: usize,
+}
+
+/// The detections found in an image sent to be searched
+#[derive(Serialize)]
+pub struct DetectResponse {
+    pub width: u32,
+    pub height: u32,
+    pub seconds: f64,
+    pub detections: Vec<LabeledDetection>,
+}
+
+/// Serves detection with the models over HTTP until the process is
+/// stopped. The endpoints are:
+///   - GET /health: the status of the server
+///   - GET /models: the class, window and number of stages of each model
+///   - POST /detect: detects objects in the PNG or JPEG image in the
+///     body of the request. Pass ?nms=IOU to suppress overlapping
+///     detections and ?classes=a,b to only use some models
+pub fn run(models: &[Model<Cascade>], options: &ServeOptions) {
+    let server = Server::http(&options.address).unwrap_or_else(|e| {
+        panic!("Unable to listen on {}: {}", options.address, e)
+    });
+    println!("Listening on http://{}", server.server_addr());
+
+    std::thread::scope(|s| {
+        for _ in 0..options.threads.max(1) {
+            s.spawn(|| {
+                for request in server.incoming_requests() {
+                    handle(models, options, request);
+                }
+            });
+        }
+    });
+}
+
+/// Responds to a request with json and logs it
+fn handle(models: &[Model<Cascade>], options: &ServeOptions, mut request: Request) {
+    let start = Instant::now();
+    let url = request.url().to_owned();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let (status, body) = match (request.method(), path) {
+        (Method::Get, "/health") => {
+            (200, json!({ "status": "ok", "models": models.len() }))
+        },
+        (Method::Get, "/models") => {
+            let info: Vec<ModelInfo> = models
+                .iter()
+                .map(|m| ModelInfo {
+                    label: &m.label,
+                    window: m.window,
+                    num_stages: m.classifier.num_stages(),
+                })
+                .collect();
+            (200, json!(info))
+        },
+        (Method::Post, "/detect") => {
+            match detect_upload(models, options, &mut request, query) {
+                Ok(response) => (200, json!(response)),
+                Err(e) => (400, json!({ "error": e })),
+            }
+        },
+        (_, "/health") | (_, "/models") | (_, "/detect") => {
+            (405, json!({ "error": "Method not allowed" }))
+        },
+        _ => (404, json!({ "error": "Not found" })),
+    };
+
+    println!(
+        "{} {} {} ({:.3}s)",
+        request.method(), url, status, start.elapsed().as_secs_f64(),
+    );
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(header);
+    if let Err(e) = request.respond(response) {
+        println!("Unable to respond to {}: {}", url, e);
+    }
+}
+
+/// Detects objects in the image uploaded in the body of a request
+fn detect_upload(
+    models: &[Model<Cascade>],
+    options: &ServeOptions,
+    request: &mut Request,
+    query: &str,
+) -> Result<DetectResponse, String> {
+    let start = Instant::now();
+    let mut nms = options.nms;
+    let mut classes: Option<Vec<String>> = None;
+    for param in query.split('&').filter(|p| !p.is_empty()) {
+        match param.split_once('=') {
+            Some(("nms", v)) => {
+                nms = Some(v.parse().map_err(|_| "nms must be a number")?);
+            },
+            Some(("classes", v)) => {
+                classes = Some(v.split(',').map(String::from).collect());
+            },
+            _ => return Err(format!("Unknown parameter: {}", param)),
+        }
+    }
+
+    let mut bytes = Vec::<u8>::new();
+    request
+        .as_reader()
+        .take(MAX_UPLOAD as u64 + 1)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Unable to read image: {}", e))?;
+    if bytes.len() > MAX_UPLOAD {
+        return Err(format!("Images must be at most {} bytes", MAX_UPLOAD));
+    }
+    let img = ColorImage::from(DynamicImage::from_bytes(&bytes)?);
+    Ok(detect_classes(models, &img, nms, classes.as_deref(), start))
+}
+
+/// Detects objects in an image with the models of the given classes,
+/// or with every model if no classes are given. Overlapping detections
+/// of a class are suppressed if nms is given. The time taken is
+/// counted from start
+pub fn detect_classes(
+    models: &[Model<Cascade>],
+    img: &ColorImage,
+    nms: Option<f64>,
+    classes: Option<&[String]>,
+    start: Instant,
+) -> DetectResponse {
+    // Every model searches the same integral image
+    let ii = IntegralImage::from(img);
+    let mut detections = Vec::<LabeledDetection>::new();
+    let used = models
+        .iter()
+        .filter(|m| classes.is_none_or(|c| c.contains(&m.label)));
+    for model in used {
+        let mut found = detect(&model.classifier, model.window, &ii);
+        if let Some(max_iou) = nms {
+            found = non_max_suppression(found, max_iou);
+        }
+        detections.extend(model.labeled(found));
+    }
+
+    DetectResponse {
+        width: img.width(),
+        height: img.height(),
+        seconds: start.elapsed().as_secs_f64(),
+        detections,
+    }
+}
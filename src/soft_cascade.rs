@@ -0,0 +1,224 @@
+use std::time::Instant;
+
+use rand::{rngs::StdRng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Cascade, Classifier, FeaturePool, ImageData, IntegralImageTrait,
+    OrderedF64, Profiler, TrainingConfig, WeakLearner, WindowSize,
+};
+
+/// A soft cascade (one long boosted sequence of weak learners). The
+/// running sum of the weighted outputs is compared to a rejection
+/// threshold after every weak learner, so most windows are rejected
+/// after only a few features
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SoftCascade {
+    /// The width and height of the window the soft cascade was built
+    /// for
+    window: [WindowSize; 2],
+
+    wcs: Vec<WeakLearner>,
+    weights: Vec<f64>,
+
+    /// The running sum needed after each weak learner to keep an image
+    rejection: Vec<f64>,
+} impl SoftCascade {
+    /// Builds a soft cascade of a given number of weak learners on the
+    /// training images and calibrates its rejection thresholds on the
+    /// validation images to reach a target detection rate. Fails before
+    /// training if the length is 0 or there are no validation objects
+    pub fn build(
+        mut train: Vec<ImageData>,
+        valid: &[ImageData],
+        length: usize,
+        target_detection: f64,
+        config: &TrainingConfig,
+    ) -> Result<SoftCascade, String> {
+        if length == 0 {
+            return Err("A soft cascade needs at least one weak learner".into());
+        }
+        if !valid.iter().any(|data| data.is_object) {
+            return Err(
+                "The validation images have no objects to calibrate the \
+                soft cascade with".into(),
+            );
+        }
+        ImageData::init_weights(&mut train);
+
+        // Get the features to build weak learners from
+        let mut pool = FeaturePool::new(config, &train);
+
+        let mut sc = SoftCascade {
+            window: config.window(),
+            wcs: Vec::with_capacity(length),
+            weights: Vec::with_capacity(length),
+            rejection: Vec::new(),
+        };
+        let mut rng = StdRng::seed_from_u64(config.sample_seed);
+        for i in 1..=length {
+            ImageData::normalize_weights(&mut train);
+            println!("Choosing Weak Classifier {} of {}", i, length);
+            let (wl, weight) =
+                WeakLearner::boost(config, &mut pool, &mut train, &mut rng);
+            sc.wcs.push(wl);
+            sc.weights.push(weight);
+        }
+
+        println!("{:-^30}", " Calibrating Thresholds ");
+        sc.calibrate(valid, target_detection)?;
+        Ok(sc)
+    }
+
+    /// Sets the rejection thresholds by direct backward pruning. The
+    /// final threshold keeps the target fraction of the objects in a
+    /// set, and every earlier threshold is the lowest running sum of
+    /// those objects so none of them are rejected early. Thresholds are
+    /// lowered slightly so rounding errors do not reject ties. Fails if
+    /// the set has no objects
+    pub fn calibrate(
+        &mut self,
+        set: &[ImageData],
+        target_detection: f64,
+    ) -> Result<(), String> {
+        let mut sums: Vec<Vec<f64>> = set
+            .iter()
+            .filter(|data| data.is_object)
+            .map(|data| self.running_sums(&data.image))
+            .collect();
+        if sums.is_empty() {
+            return Err("Objects are needed to calibrate the soft cascade".into());
+        }
+        sums.sort_by_key(|s| {
+            std::cmp::Reverse(OrderedF64(*s.last().unwrap_or(&0.0)))
+        });
+        let needed = (target_detection * sums.len() as f64).ceil() as usize;
+        sums.truncate(needed.clamp(1, sums.len()));
+
+        self.rejection = (0..self.wcs.len())
+            .map(|t| {
+                sums.iter()
+                    .map(|s| s[t])
+                    .min_by_key(|sum| OrderedF64(*sum))
+                    .unwrap()
+                    - 1e-9
+            })
+            .collect();
+        Ok(())
+    }
+
+    /// Gets the running sum of the weighted outputs after each weak
+    /// learner
+    fn running_sums(&self, img: &impl IntegralImageTrait) -> Vec<f64> {
+        let mut sum = 0.0;
+        self.wcs
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(wl, weight)| {
+                sum += weight * wl.output(img);
+                sum
+            })
+            .collect()
+    }
+
+    /// Runs the soft cascade over an image. Returns the margin of the
+    /// final sum over the final threshold, or None if the image is
+    /// rejected, along with the number of features evaluated
+    pub fn evaluate(&self, img: &impl IntegralImageTrait) -> (Option<f64>, usize) {
+        let mut sum = 0.0;
+        let mut features = 0;
+        let learners = self.wcs.iter().zip(self.weights.iter());
+        for ((wl, weight), threshold) in learners.zip(self.rejection.iter()) {
+            sum += weight * wl.output(img);
+            features += wl.num_features();
+            if sum < *threshold { return (None, features) }
+        }
+        let margin = sum - self.rejection.last().copied().unwrap_or(0.0);
+        (Some(margin), features)
+    }
+
+    /// Gets the width and height of the window the soft cascade was
+    /// built for
+    pub fn window(&self) -> [u32; 2] {
+        [u32::from(self.window[0]), u32::from(self.window[1])]
+    }
+
+    /// Gets the number of weak learners in the soft cascade
+    pub fn num_learners(&self) -> usize { self.wcs.len() }
+} impl Classifier for SoftCascade {
+    fn classify(&self, img: &impl IntegralImageTrait) -> bool {
+        self.evaluate(img).0.is_some()
+    }
+
+    /// Scores an image by the margin of the final running sum
+    fn score(&self, img: &impl IntegralImageTrait) -> Option<f64> {
+        self.evaluate(img).0
+    }
+
+    /// Gets the fraction of the rejection thresholds an image passes
+    fn progress(&self, img: &impl IntegralImageTrait) -> f64 {
+        let passed = self.running_sums(img)
+            .iter()
+            .zip(self.rejection.iter())
+            .take_while(|(sum, threshold)| sum >= threshold)
+            .count();
+        passed as f64 / self.rejection.len().max(1) as f64
+    }
+}
+
+/// The accuracy and speed of a classifier over a set of validation
+/// images
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SpeedReport {
+    pub model: String,
+    pub detection_rate: f64,
+    pub false_pos_rate: f64,
+    pub features_per_window: f64,
+    pub seconds_per_window: f64,
+} impl SpeedReport {
+    /// Tests a soft cascade over a set of images
+    pub fn soft(soft: &SoftCascade, set: &[ImageData]) -> SpeedReport {
+        let (false_pos_rate, detection_rate) = soft.test(set);
+        let start = Instant::now();
+        let features: usize = set
+            .iter()
+            .map(|data| soft.evaluate(&data.image).1)
+            .sum();
+        SpeedReport {
+            model: "soft cascade".to_owned(),
+            detection_rate,
+            false_pos_rate,
+            features_per_window: features as f64 / set.len() as f64,
+            seconds_per_window: start.elapsed().as_secs_f64()
+                / set.len() as f64,
+        }
+    }
+
+    /// Tests a staged cascade over a set of images
+    pub fn staged(cascade: &Cascade, set: &[ImageData]) -> SpeedReport {
+        let (false_pos_rate, detection_rate) = cascade.test(set);
+        let start = Instant::now();
+        set.iter().for_each(|data| { cascade.classify(&data.image); });
+        let seconds = start.elapsed().as_secs_f64();
+        let mut profiler = Profiler::new(cascade);
+        for data in set {
+            profiler.record(&data.image, Some(data.is_object));
+        }
+        SpeedReport {
+            model: "cascade".to_owned(),
+            detection_rate,
+            false_pos_rate,
+            features_per_window: profiler.finish().avg_features_per_window,
+            seconds_per_window: seconds / set.len() as f64,
+        }
+    }
+
+    pub fn print(&self) {
+        println!(
+            "{}: Detection Rate: {} False Positive Rate: {} \
+            Features Per Window: {:.3} Seconds Per Window: {:.3e}",
+            self.model, self.detection_rate, self.false_pos_rate,
+            self.features_per_window, self.seconds_per_window,
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use super::{Cascade, Classifier, IntegralImageTrait};
+
+/// Statistics of how a single stage of a cascade treats the windows
+/// that reach it. Rates of positive and negative windows are only
+/// known when the windows are labeled. The linear stage of a cascade
+/// has no weak learners
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StageStats {
+    #[serde(default)]
+    pub linear: bool,
+    pub num_wcs: usize,
+    #[serde(default)]
+    pub num_features: usize,
+    pub reached: usize,
+    pub passed: usize,
+    pub pass_rate: f64,
+    pub pos_pass_rate: Option<f64>,
+    pub neg_pass_rate: Option<f64>,
+    pub cumulative_pass_rate: f64,
+    pub cumulative_fpr: Option<f64>,
+    pub seconds: f64,
+}
+
+/// Statistics of running a cascade over a set of windows
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CascadeStats {
+    pub num_windows: usize,
+    pub avg_features_per_window: f64,
+    pub stages: Vec<StageStats>,
+} impl CascadeStats {
+    /// Prints the statistics as a table
+    pub fn print_table(&self) {
+        println!("Windows: {}", self.num_windows);
+        println!(
+            "Average Features Per Window: {:.3}",
+            self.avg_features_per_window,
+        );
+        println!(
+            "{:>6} {:>5} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "Stage", "WCs", "Features", "Reached", "Pass", "Pos Pass",
+            "Neg Pass", "Cum. FPR", "Seconds",
+        );
+        let fmt = |r: Option<f64>| r.map_or("-".to_owned(), |r| format!("{:.4}", r));
+        for (i, s) in self.stages.iter().enumerate() {
+            let (stage, num_wcs) = if s.linear {
+                ("Linear".to_owned(), "-".to_owned())
+            } else { ((i + 1).to_string(), s.num_wcs.to_string()) };
+            println!(
+                "{:>6} {:>5} {:>8} {:>10} {:>10.4} {:>10} {:>10} {:>10} {:>10.4}",
+                stage, num_wcs, s.num_features, s.reached, s.pass_rate,
+                fmt(s.pos_pass_rate), fmt(s.neg_pass_rate),
+                fmt(s.cumulative_fpr), s.seconds,
+            );
+        }
+    }
+}
+
+/// Number of windows that reached and passed a stage
+#[derive(Default, Clone)]
+struct Counts {
+    reached: [usize; 3],
+    passed: [usize; 3],
+    time: Duration,
+} impl Counts {
+    /// Records a window of a label reaching the stage and gets whether
+    /// or not it passed
+    fn record(&mut self, l: usize, classify: impl FnOnce() -> bool) -> bool {
+        self.reached[l] += 1;
+        let start = Instant::now();
+        let passed = classify();
+        self.time += start.elapsed();
+        if passed { self.passed[l] += 1 }
+        passed
+    }
+}
+
+/// Records how each stage of a cascade, including its linear stage,
+/// treats a set of windows
+pub struct Profiler<'a> {
+    cascade: &'a Cascade,
+    counts: Vec<Counts>,
+    windows: [usize; 3],
+    features: usize,
+} impl<'a> Profiler<'a> {
+    pub fn new(cascade: &'a Cascade) -> Profiler<'a> {
+        Profiler {
+            cascade,
+            counts: vec![Counts::default(); num_stages(cascade)],
+            windows: [0; 3],
+            features: 0,
+        }
+    }
+
+    /// Runs the cascade over a window and records the result. The
+    /// label is whether or not the window contains the object, if
+    /// known
+    pub fn record(
+        &mut self,
+        img: &impl IntegralImageTrait,
+        label: Option<bool>,
+    ) {
+        let l = match label { Some(true) => 0, Some(false) => 1, None => 2 };
+        self.windows[l] += 1;
+        let mut counts = self.counts.iter_mut();
+        for sc in self.cascade.stages() {
+            self.features += sc.num_features();
+            let passed = counts.next().unwrap().record(l, || sc.classify(img));
+            if !passed { return }
+        }
+        if let Some(stage) = self.cascade.linear_stage() {
+            self.features += stage.num_features();
+            let window = self.cascade.window();
+            counts.next().unwrap().record(l, || stage.classify(img, window));
+        }
+    }
+
+    /// Calculates the statistics of the recorded windows
+    pub fn finish(self) -> CascadeStats {
+        let rate = |a: usize, b: usize| {
+            if b == 0 { None } else { Some(a as f64 / b as f64) }
+        };
+        let num_windows: usize = self.windows.iter().sum();
+
+        // The number of weak learners and features of each stage
+        let sizes = self.cascade
+            .stages()
+            .iter()
+            .map(|sc| (false, sc.num_wcs(), sc.num_features()))
+            .chain(self.cascade.linear_stage().map(|stage| {
+                (true, 0, stage.num_features())
+            }));
+        let stages = sizes
+            .zip(self.counts.iter())
+            .map(|((linear, num_wcs, num_features), c)| {
+                let reached: usize = c.reached.iter().sum();
+                let passed: usize = c.passed.iter().sum();
+                StageStats {
+                    linear,
+                    num_wcs,
+                    num_features,
+                    reached,
+                    passed,
+                    pass_rate: rate(passed, reached).unwrap_or(0.0),
+                    pos_pass_rate: rate(c.passed[0], c.reached[0]),
+                    neg_pass_rate: rate(c.passed[1], c.reached[1]),
+                    cumulative_pass_rate: rate(passed, num_windows)
+                        .unwrap_or(0.0),
+                    cumulative_fpr: rate(c.passed[1], self.windows[1]),
+                    seconds: c.time.as_secs_f64(),
+                }
+            })
+            .collect();
+        CascadeStats {
+            num_windows,
+            avg_features_per_window: rate(self.features, num_windows)
+                .unwrap_or(0.0),
+            stages,
+        }
+    }
+}
+
+/// Gets the number of stages of a cascade, counting its linear stage
+fn num_stages(cascade: &Cascade) -> usize {
+    cascade.num_stages() + usize::from(cascade.linear_stage().is_some())
+}
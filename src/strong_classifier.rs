@@ -1,53 +1,65 @@
+use rand::rngs::StdRng;
 use serde::{Deserialize, Serialize};
 use super::{
     ImageData, 
-    WeakClassifier,
+    FeaturePool,
+    WeakLearner,
     Classifier,
-    MAX_FALSE_POS,
+    TrainingConfig,
     IntegralImageTrait,
+    OrderedF64,
 };
 
-/// A strong classifier (made up of weighted weak classifiers)
+/// A strong classifier (made up of weighted weak learners)
 #[derive(Debug, Serialize, Deserialize)]
 pub struct StrongClassifier {
-    wcs: Vec<WeakClassifier>,
+    wcs: Vec<WeakLearner>,
     weights: Vec<f64>,
+
+    /// The weighted vote needed to classify an image as the object.
+    /// Defaults to half of the sum of the weights of the discrete weak
+    /// learners
+    #[serde(default)]
+    threshold: Option<f64>,
 } impl StrongClassifier {
 
-    /// Builds a strong classifier out of weak classifiers
-    /// from a list of potential weak classifiers
+    /// Builds a strong classifier out of weak learners built from a
+    /// pool of features
     pub fn build(
-        all_wcs: &mut [WeakClassifier],
+        pool: &mut FeaturePool,
         set: &mut [ImageData],
         num_wcs: Option<usize>,
+        config: &TrainingConfig,
+        rng: &mut StdRng,
     ) -> StrongClassifier {
 
-        let mut wcs = Vec::<WeakClassifier>::new();
-        let mut weights = Vec::<f64>::new();
+        let mut sc = StrongClassifier {
+            wcs: Vec::new(),
+            weights: Vec::new(),
+            threshold: None,
+        };
 
-        let mut false_pos = 0.0;
         let mut i = 1;
         loop {
             // Normalize weights
             ImageData::normalize_weights(set);
 
-            // Calculate Thresholds
-            WeakClassifier::calculate_thresholds(all_wcs, set);
-
             // Tell user that we are finding new weak classifier
             println!(
                 "Choosing Weak Classifier {}{}{}", 
-                i.to_string(),
+                i,
                 num_wcs.map_or("", |_| " of "),
                 num_wcs.map_or("".to_owned(), |n| n.to_string()),
             );
             
-            // Get the best weak classifier
-            let wc = WeakClassifier::get_best(&all_wcs, set);
+            // Get the best weak learner and update the weights
+            let (wl, weight) = WeakLearner::boost(config, pool, set, rng);
+            sc.weights.push(weight);
+            sc.wcs.push(wl);
 
-            // Update the weights
-            weights.push(wc.update_weights(set));
-            wcs.push(wc);
+            // Lower the threshold until the detection rate is met
+            sc.threshold = sc.detection_threshold(set, config.min_detection);
+            let (false_pos, _) = sc.test(set);
             
             // Print informattion about current cascade
             println!("Current False Positive Rate: {}", 
@@ -55,23 +67,115 @@ pub struct StrongClassifier {
 
             // Determine whether or not to break
             let should_break = num_wcs.map_or_else(
-                || false_pos <= MAX_FALSE_POS, |n| i == n);
+                || false_pos <= config.max_false_pos, |n| i == n);
             if should_break { break }
 
             i+=1;
         }
 
-        // Build and return the strong classifier
-        StrongClassifier { wcs, weights }
+        // Return the strong classifier
+        sc
     }
-} impl Classifier for StrongClassifier {
-    fn classify(&self, img: &impl IntegralImageTrait) -> bool {
+
+    /// Finds the highest threshold (no higher than the default
+    /// threshold) that classifies at least the given fraction of the
+    /// objects in a set as objects
+    fn detection_threshold(
+        &self,
+        set: &[ImageData],
+        min_detection: f64,
+    ) -> Option<f64> {
+        let mut votes: Vec<f64> = set
+            .iter()
+            .filter(|data| data.is_object)
+            .map(|data| self.vote(&data.image))
+            .collect();
+        if votes.is_empty() { return None }
+        votes.sort_by_key(|v| std::cmp::Reverse(OrderedF64(*v)));
+
+        let needed = (min_detection * votes.len() as f64).ceil() as usize;
+        let threshold = votes[needed.clamp(1, votes.len()) - 1];
+        Some(f64::min(threshold, self.default_threshold()))
+    }
+
+    /// Gets half of the sum of the weights of the discrete weak
+    /// learners. Real valued weak learners are centered on zero
+    fn default_threshold(&self) -> f64 {
         self.wcs
             .iter()
             .zip(self.weights.iter())
-            .filter(|(wc, _)| wc.classify(img))
+            .filter(|(wl, _)| wl.is_discrete())
             .map(|(_, weight)| weight)
             .sum::<f64>()
-            >= self.weights.iter().sum::<f64>() / 2.0
+            / 2.0
+    }
+
+    /// Gets each weak learner along with its weight
+    pub fn weak_learners(&self) -> impl Iterator<Item = (&WeakLearner, f64)> {
+        self.wcs.iter().zip(self.weights.iter().copied())
+    }
+
+    /// Gets the number of weak learners
+    pub fn num_wcs(&self) -> usize { self.wcs.len() }
+
+    /// Gets the number of features evaluated to classify an image
+    pub fn num_features(&self) -> usize {
+        self.wcs.iter().map(|wl| wl.num_features()).sum()
+    }
+
+    /// Gets the weighted vote of the weak learners
+    fn vote(&self, img: &impl IntegralImageTrait) -> f64 {
+        self.wcs
+            .iter()
+            .zip(self.weights.iter())
+            .map(|(wl, weight)| weight * wl.output(img))
+            .sum::<f64>()
+    }
+
+    /// Gets the margin between the weighted vote of the weak
+    /// classifiers and the threshold of the strong classifier
+    pub fn margin(&self, img: &impl IntegralImageTrait) -> f64 {
+        let threshold = self.threshold
+            .unwrap_or_else(|| self.default_threshold());
+        self.vote(img) - threshold
+    }
+} impl Classifier for StrongClassifier {
+    fn classify(&self, img: &impl IntegralImageTrait) -> bool {
+        self.margin(img) >= 0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::weak_classifier::tests::{data, fit};
+
+    #[test]
+    fn detection_threshold_keeps_the_minimum_detection_rate() {
+        // Every value is an object. One weak classifier votes for the
+        // values below 5 and the other for those below 8, so the votes
+        // are 2 for 0 to 4, 1 for 5 to 7 and 0 for 8 and 9
+        let vote_below = |t: u8| {
+            let set: Vec<ImageData> = (0..10)
+                .map(|v| data(v, v < t, 0.1))
+                .collect();
+            WeakLearner::Discrete(fit(&set))
+        };
+        let sc = StrongClassifier {
+            wcs: vec![vote_below(5), vote_below(8)],
+            weights: vec![1.0, 1.0],
+            threshold: None,
+        };
+        let set: Vec<ImageData> = (0..10).map(|v| data(v, true, 0.1)).collect();
+
+        // The threshold is lowered until enough objects pass, but never
+        // raised above half of the sum of the weights
+        assert_eq!(sc.detection_threshold(&set, 0.5), Some(1.0));
+        assert_eq!(sc.detection_threshold(&set, 0.8), Some(1.0));
+        assert_eq!(sc.detection_threshold(&set, 0.9), Some(0.0));
+        assert_eq!(sc.detection_threshold(&set[..0], 0.9), None);
+
+        let sc = StrongClassifier { threshold: Some(0.0), ..sc };
+        assert_eq!(sc.test(&set).1, 1.0);
     }
 }
@@ -0,0 +1,199 @@
+use std::time::Instant;
+
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Boosting, Cascade, Classifier, Curves, ImageData, OrderedF64, Profiler,
+    TrainingConfig, WindowSize,
+};
+
+/// The values to try for each training setting in a sweep
+pub struct SweepRanges {
+    pub ws: Vec<WindowSize>,
+    pub max_false_pos: Vec<f64>,
+    pub min_detection: Vec<f64>,
+    pub max_stages: Vec<Option<usize>>,
+    pub filter: Vec<Option<f64>>,
+    pub boosting: Vec<Boosting>,
+} impl SweepRanges {
+    /// Gets every combination of the settings, using a base config
+    /// for all other settings
+    pub fn configs(&self, base: &TrainingConfig) -> Vec<TrainingConfig> {
+        let mut configs = Vec::<TrainingConfig>::new();
+        for &ws in self.ws.iter() {
+            for &max_false_pos in self.max_false_pos.iter() {
+                for &min_detection in self.min_detection.iter() {
+                    for &max_stages in self.max_stages.iter() {
+                        for &filter in self.filter.iter() {
+                            for &boosting in self.boosting.iter() {
+                                configs.push(TrainingConfig {
+                                    ws,
+                                    max_false_pos,
+                                    min_detection,
+                                    max_stages,
+                                    filter,
+                                    boosting,
+                                    ..base.clone()
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        configs
+    }
+}
+
+/// The performance of a training config on the validation images
+#[derive(Serialize, Deserialize, Debug)]
+pub struct SweepEntry {
+    pub config: TrainingConfig,
+    pub num_stages: usize,
+    pub detection_rate: f64,
+    pub false_pos_rate: f64,
+    pub detection_rate_at_target: f64,
+    pub features_per_window: f64,
+    pub seconds_per_window: f64,
+    pub detection_rank: usize,
+    pub speed_rank: usize,
+}
+
+/// Training configs ranked by their performance on validation images
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Leaderboard {
+    pub target_fpr: f64,
+    pub entries: Vec<SweepEntry>,
+} impl Leaderboard {
+    /// Splits a set of images into training and validation images.
+    /// Objects and others are split separately so both sets have about
+    /// the same ratio of objects to others
+    pub fn split(
+        set: Vec<ImageData>,
+        validation: f64,
+        seed: u64,
+    ) -> (Vec<ImageData>, Vec<ImageData>) {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let (mut objects, mut others): (Vec<_>, Vec<_>) =
+            set.into_iter().partition(|data| data.is_object);
+        objects.shuffle(&mut rng);
+        others.shuffle(&mut rng);
+
+        let n_objects = (validation * objects.len() as f64).round() as usize;
+        let n_others = (validation * others.len() as f64).round() as usize;
+        let mut train = objects.split_off(n_objects);
+        train.extend(others.split_off(n_others));
+        objects.extend(others);
+        (train, objects)
+    }
+
+    /// Trains a cascade for every config and ranks the configs by
+    /// their detection rate on the validation images at the target
+    /// false positive rate, and by the speed of detection. Images are
+    /// resized from the given images to the window of each config
+    pub fn run(
+        train: &[ImageData],
+        valid: &[ImageData],
+        configs: Vec<TrainingConfig>,
+        target_fpr: f64,
+    ) -> Leaderboard {
+        let num_configs = configs.len();
+        let mut entries = Vec::<SweepEntry>::with_capacity(num_configs);
+        for (i, config) in configs.into_iter().enumerate() {
+            println!(
+                "{:-^30}",
+                format!(" Configuration {} of {} ", i + 1, num_configs),
+            );
+            println!("{}", serde_json::to_string(&config).unwrap());
+            let window = config.window();
+            let resize = |set: &[ImageData]| -> Vec<ImageData> {
+                set.par_iter().map(|d| d.resized(window)).collect()
+            };
+            let valid = resize(valid);
+            let cascade = Cascade::build(resize(train), &config);
+            entries.push(SweepEntry::new(config, &cascade, &valid, target_fpr));
+        }
+
+        // Rank the configs by detection rate, breaking ties by speed
+        entries.sort_by_key(|e| OrderedF64(e.features_per_window));
+        entries.sort_by_key(|e| {
+            std::cmp::Reverse(OrderedF64(e.detection_rate_at_target))
+        });
+        let mut by_speed: Vec<usize> = (0..entries.len()).collect();
+        by_speed.sort_by_key(|&i| OrderedF64(entries[i].features_per_window));
+        for (rank, i) in by_speed.into_iter().enumerate() {
+            entries[i].speed_rank = rank + 1;
+        }
+        for (rank, e) in entries.iter_mut().enumerate() {
+            e.detection_rank = rank + 1;
+        }
+
+        Leaderboard { target_fpr, entries }
+    }
+
+    /// Formats the leaderboard as csv
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "detection_rank,speed_rank,ws,max_false_pos,min_detection,\
+            max_stages,filter,boosting,num_stages,detection_rate,\
+            false_pos_rate,detection_rate_at_target,features_per_window,\
+            seconds_per_window\n",
+        );
+        let opt = |v: Option<String>| v.unwrap_or_default();
+        for e in self.entries.iter() {
+            let c = &e.config;
+            csv += &format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                e.detection_rank, e.speed_rank, c.ws, c.max_false_pos,
+                c.min_detection, opt(c.max_stages.map(|n| n.to_string())),
+                opt(c.filter.map(|f| f.to_string())),
+                serde_json::to_string(&c.boosting).unwrap().trim_matches('"'),
+                e.num_stages, e.detection_rate, e.false_pos_rate,
+                e.detection_rate_at_target, e.features_per_window,
+                e.seconds_per_window,
+            );
+        }
+        csv
+    }
+}
+
+impl SweepEntry {
+    fn new(
+        config: TrainingConfig,
+        cascade: &Cascade,
+        valid: &[ImageData],
+        target_fpr: f64,
+    ) -> SweepEntry {
+        let (false_pos_rate, detection_rate) = cascade.test(valid);
+
+        // Find the detection rate at the target false positive rate by
+        // sweeping the threshold of the last strong classifier
+        let scores: Vec<(Option<f64>, bool)> = valid
+            .iter()
+            .map(|d| {
+                (cascade.partial_score(&d.image, cascade.num_stages()), d.is_object)
+            })
+            .collect();
+        let curves = Curves::new(cascade.num_stages(), &scores);
+
+        // Time the cascade over the validation images
+        let mut profiler = Profiler::new(cascade);
+        let start = Instant::now();
+        for d in valid { profiler.record(&d.image, Some(d.is_object)); }
+        let seconds = start.elapsed().as_secs_f64();
+
+        SweepEntry {
+            config,
+            num_stages: cascade.num_stages(),
+            detection_rate,
+            false_pos_rate,
+            detection_rate_at_target: curves.detection_rate_at(target_fpr),
+            features_per_window: profiler.finish().avg_features_per_window,
+            seconds_per_window: seconds / valid.len() as f64,
+            detection_rank: 0,
+            speed_rank: 0,
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+use super::{Feature, Window, WindowSize};
+
+/// The shapes of Haar-like features
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    HorizontalTwo,
+    VerticalTwo,
+    HorizontalThree,
+    VerticalThree,
+    Four,
+
+    /// A center cell minus the ring of cells around it
+    CenterSurround,
+
+    /// A pattern defined by the user
+    Custom(Pattern),
+} impl Shape {
+    /// Gets the pattern of rectangles of the shape
+    pub fn pattern(&self) -> Pattern {
+        let pattern = |grid, white: &[[WindowSize; 4]], black: &[_]| Pattern {
+            name: self.name(),
+            grid,
+            white: white.to_vec(),
+            black: black.to_vec(),
+        };
+        match self {
+            Shape::HorizontalTwo => {
+                pattern([2, 1], &[[0, 0, 1, 1]], &[[1, 0, 1, 1]])
+            },
+            Shape::VerticalTwo => {
+                pattern([1, 2], &[[0, 0, 1, 1]], &[[0, 1, 1, 1]])
+            },
+            Shape::HorizontalThree => pattern(
+                [3, 1], &[[0, 0, 1, 1], [2, 0, 1, 1]], &[[1, 0, 1, 1]],
+            ),
+            Shape::VerticalThree => pattern(
+                [1, 3], &[[0, 0, 1, 1], [0, 2, 1, 1]], &[[0, 1, 1, 1]],
+            ),
+            Shape::Four => pattern(
+                [2, 2],
+                &[[0, 0, 1, 1], [1, 1, 1, 1]],
+                &[[1, 0, 1, 1], [0, 1, 1, 1]],
+            ),
+            // The center is counted twice so that the whole grid
+            // subtracts it once
+            Shape::CenterSurround => pattern(
+                [3, 3], &[[0, 0, 3, 3]], &[[1, 1, 1, 1], [1, 1, 1, 1]],
+            ),
+            Shape::Custom(pattern) => pattern.clone(),
+        }
+    }
+
+    /// Gets the name of the shape
+    pub fn name(&self) -> String {
+        match self {
+            Shape::HorizontalTwo => "horizontal_two".to_owned(),
+            Shape::VerticalTwo => "vertical_two".to_owned(),
+            Shape::HorizontalThree => "horizontal_three".to_owned(),
+            Shape::VerticalThree => "vertical_three".to_owned(),
+            Shape::Four => "four".to_owned(),
+            Shape::CenterSurround => "center_surround".to_owned(),
+            Shape::Custom(pattern) => pattern.name.clone(),
+        }
+    }
+}
+
+/// Black and white rectangles on a grid of equally sized cells. Each
+/// rectangle is given in cells as [x, y, width, height], and a feature
+/// is the sum of its black rectangles minus the sum of its white ones
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pattern {
+    pub name: String,
+
+    /// The number of columns and rows of cells
+    pub grid: [WindowSize; 2],
+
+    /// One or two white rectangles
+    pub white: Vec<[WindowSize; 4]>,
+
+    /// One or two black rectangles
+    pub black: Vec<[WindowSize; 4]>,
+} impl Pattern {
+    /// Panics if the pattern cannot be made into features
+    fn validate(&self) {
+        for rects in [&self.white, &self.black] {
+            if rects.is_empty() || rects.len() > 2 {
+                panic!(
+                    "Template {} must have one or two white and black \
+                    rectangles",
+                    self.name,
+                );
+            }
+            for &[x, y, w, h] in rects.iter() {
+                let fits = x + w <= self.grid[0] && y + h <= self.grid[1];
+                if w == 0 || h == 0 || !fits {
+                    panic!(
+                        "Template {} has a rectangle outside its grid",
+                        self.name,
+                    );
+                }
+            }
+        }
+    }
+
+    /// Gets the feature of the pattern with its top left corner at a
+    /// position and cells of a given width and height
+    fn feature(
+        &self,
+        x: WindowSize,
+        y: WindowSize,
+        w: WindowSize,
+        h: WindowSize,
+    ) -> Feature {
+        let rect = |r: &[WindowSize; 4]| {
+            Window::new(x + r[0] * w, y + r[1] * h, r[2] * w, r[3] * h)
+        };
+        let rects = |rs: &[[WindowSize; 4]]| (rect(&rs[0]), rs.get(1).map(rect));
+        Feature {
+            black: rects(&self.black),
+            white: rects(&self.white),
+            channel: None,
+        }
+    }
+}
+
+/// A shape of Haar-like feature along with how its features are
+/// generated. Features are made for every cell size from the minimum
+/// in increments of the size step, at positions a stride apart
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Template {
+    pub shape: Shape,
+
+    /// The minimum width and height of a cell
+    #[serde(default = "default_min_size")]
+    pub min_size: [WindowSize; 2],
+
+    /// The distance between positions of features
+    #[serde(default = "default_increment")]
+    pub stride: WindowSize,
+
+    /// The increment between sizes of cells
+    #[serde(default = "default_increment")]
+    pub step: WindowSize,
+} impl Template {
+    /// Makes a template that generates every feature of a shape
+    pub fn exhaustive(shape: Shape) -> Template {
+        Template {
+            shape,
+            min_size: default_min_size(),
+            stride: default_increment(),
+            step: default_increment(),
+        }
+    }
+
+    /// Gets the templates of the five shapes used by Viola and Jones
+    pub fn viola_jones() -> Vec<Template> {
+        vec![
+            Template::exhaustive(Shape::HorizontalTwo),
+            Template::exhaustive(Shape::VerticalTwo),
+            Template::exhaustive(Shape::HorizontalThree),
+            Template::exhaustive(Shape::VerticalThree),
+            Template::exhaustive(Shape::Four),
+        ]
+    }
+
+    /// Reads a list of templates from a json file
+    pub fn from_file(path: &str) -> Vec<Template> {
+        let data = fs::read_to_string(path)
+            .expect("Unable to read feature templates");
+        serde_json::from_str(&data).expect("Unable to parse feature templates")
+    }
+
+    /// Gets every feature of the template that fits in a window. Like
+    /// the rest of the features, they must end before the last row and
+    /// column of the window
+    pub fn features(&self, window: [WindowSize; 2]) -> Vec<Feature> {
+        let pattern = self.shape.pattern();
+        pattern.validate();
+        let mut features = Vec::<Feature>::new();
+        for (w, h) in self.cell_sizes(&pattern, window) {
+            for x in self.positions(pattern.grid[0] * w, window[0]) {
+                for y in self.positions(pattern.grid[1] * h, window[1]) {
+                    features.push(pattern.feature(x, y, w, h));
+                }
+            }
+        }
+        features
+    }
+
+    /// Counts the features of the template that fit in a window
+    /// without making them
+    pub fn count(&self, window: [WindowSize; 2]) -> usize {
+        let pattern = self.shape.pattern();
+        pattern.validate();
+        self.cell_sizes(&pattern, window)
+            .map(|(w, h)| {
+                self.positions(pattern.grid[0] * w, window[0]).count()
+                    * self.positions(pattern.grid[1] * h, window[1]).count()
+            })
+            .sum()
+    }
+
+    /// Gets the width and height of every cell that fits the pattern in
+    /// a window
+    fn cell_sizes(
+        &self,
+        pattern: &Pattern,
+        window: [WindowSize; 2],
+    ) -> impl Iterator<Item = (WindowSize, WindowSize)> {
+        let step = usize::from(self.step.max(1));
+        let [cols, rows] = pattern.grid.map(u16::from);
+        let [wl, wh] = window.map(u16::from);
+        let widths = (self.min_size[0].max(1)..)
+            .take_while(move |w| cols * u16::from(*w) < wl)
+            .step_by(step);
+        let heights: Vec<WindowSize> = (self.min_size[1].max(1)..)
+            .take_while(|h| rows * u16::from(*h) < wh)
+            .step_by(step)
+            .collect();
+        widths.flat_map(move |w| heights.clone().into_iter().map(move |h| (w, h)))
+    }
+
+    /// Gets the positions along one side of a window where a feature
+    /// of the given length ends before the last row or column
+    fn positions(
+        &self,
+        len: WindowSize,
+        side: WindowSize,
+    ) -> impl Iterator<Item = WindowSize> {
+        (0..side.saturating_sub(len)).step_by(usize::from(self.stride.max(1)))
+    }
+}
+
+fn default_min_size() -> [WindowSize; 2] { [1, 1] }
+fn default_increment() -> WindowSize { 1 }
@@ -0,0 +1,232 @@
+use serde::{Deserialize, Serialize};
+
+use super::{LabeledDetection, OrderedF64, Rectangle};
+
+/// The variance added to the position and velocity of each coordinate
+/// of a track every frame, as a fraction of the size of the track
+const PROCESS_NOISE: f64 = 0.01;
+
+/// The variance of each measured coordinate of a detection, as a
+/// fraction of the size of the detection
+const MEASUREMENT_NOISE: f64 = 0.05;
+
+/// The class of a track and the rectangle where it is expected
+pub type Prediction = (String, Rectangle<u32>);
+
+/// A detection along with the track it belongs to
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TrackedDetection {
+    pub track: u64,
+
+    #[serde(flatten)]
+    pub labeled: LabeledDetection,
+}
+
+/// A Kalman filter of one coordinate that moves at a constant velocity
+#[derive(Debug, Clone)]
+struct Kalman {
+    position: f64,
+    velocity: f64,
+
+    /// The covariance of the position and velocity
+    p: [[f64; 2]; 2],
+} impl Kalman {
+    /// Starts a filter at a measured position with an unknown velocity
+    fn new(position: f64, variance: f64) -> Kalman {
+        Kalman {
+            position,
+            velocity: 0.0,
+            p: [[variance, 0.0], [0.0, 10.0 * variance]],
+        }
+    }
+
+    /// Moves the filter forward one frame
+    fn predict(&mut self, q: f64) {
+        self.position += self.velocity;
+        let [[a, b], [c, d]] = self.p;
+        self.p = [[a + b + c + d + q, b + d], [c + d, d + q]];
+    }
+
+    /// Corrects the filter with a measured position
+    fn update(&mut self, measured: f64, r: f64) {
+        let [[a, b], [c, d]] = self.p;
+        let s = a + r;
+        let (k0, k1) = (a / s, c / s);
+        let error = measured - self.position;
+        self.position += k0 * error;
+        self.velocity += k1 * error;
+        self.p = [
+            [(1.0 - k0) * a, (1.0 - k0) * b],
+            [c - k1 * a, d - k1 * b],
+        ];
+    }
+}
+
+/// An object followed from frame to frame. Its center, width and
+/// height are each followed by a Kalman filter
+#[derive(Debug, Clone)]
+struct Track {
+    id: u64,
+    label: String,
+
+    /// Filters of the center x, center y, width and height
+    filters: [Kalman; 4],
+
+    /// The number of frames the track has been matched in
+    hits: usize,
+
+    /// The number of frames since the track was last matched
+    misses: usize,
+} impl Track {
+    fn new(id: u64, d: &LabeledDetection) -> Track {
+        let noise = MEASUREMENT_NOISE * size(&d.detection.rect);
+        Track {
+            id,
+            label: d.label.clone(),
+            filters: coordinates(&d.detection.rect)
+                .map(|c| Kalman::new(c, noise)),
+            hits: 1,
+            misses: 0,
+        }
+    }
+
+    /// Gets the rectangle the track is expected at
+    fn rect(&self) -> Rectangle<u32> {
+        let [cx, cy, w, h] = self.filters.clone().map(|f| f.position.max(1.0));
+        let x = (cx - w / 2.0).max(0.0);
+        let y = (cy - h / 2.0).max(0.0);
+        Rectangle::new(x as u32, y as u32, w.round() as u32, h.round() as u32)
+    }
+
+    /// Moves the track to where it is expected in the next frame
+    fn predict(&mut self) {
+        let q = PROCESS_NOISE * size(&self.rect());
+        self.filters.iter_mut().for_each(|f| f.predict(q));
+    }
+
+    /// Corrects the track with the rectangle it was matched to
+    fn update(&mut self, rect: &Rectangle<u32>) {
+        let r = MEASUREMENT_NOISE * size(rect);
+        for (f, c) in self.filters.iter_mut().zip(coordinates(rect)) {
+            f.update(c, r);
+        }
+        self.hits += 1;
+        self.misses = 0;
+    }
+}
+
+/// Associates detections across frames and gives each object a stable
+/// track id. Each frame, tracks are moved to where their filters
+/// expect them and greedily matched to the detections of the same
+/// class they overlap most
+#[derive(Debug, Clone)]
+pub struct Tracker {
+    tracks: Vec<Track>,
+    next_id: u64,
+
+    /// The minimum intersection over union of a track and a detection
+    /// for them to be matched
+    pub min_iou: f64,
+
+    /// The number of frames a track is kept without being matched
+    pub max_age: usize,
+
+    /// The number of frames a track must be matched in before its
+    /// detections are reported
+    pub min_hits: usize,
+} impl Tracker {
+    pub fn new(min_iou: f64, max_age: usize, min_hits: usize) -> Tracker {
+        Tracker {
+            tracks: Vec::new(),
+            next_id: 1,
+            min_iou,
+            max_age,
+            min_hits: min_hits.max(1),
+        }
+    }
+
+    /// Gets the number of tracks started so far
+    pub fn num_tracks(&self) -> u64 { self.next_id - 1 }
+
+    /// Gets the classes of the live tracks and the rectangles where
+    /// they are expected in the next frame
+    pub fn predictions(&self) -> Vec<Prediction> {
+        self.tracks
+            .iter()
+            .map(|t| {
+                let mut t = t.clone();
+                t.predict();
+                (t.label.clone(), t.rect())
+            })
+            .collect()
+    }
+
+    /// Matches the detections of the next frame to tracks and returns
+    /// the detections of confirmed tracks
+    pub fn update(
+        &mut self,
+        detections: &[LabeledDetection],
+    ) -> Vec<TrackedDetection> {
+        self.tracks.iter_mut().for_each(Track::predict);
+
+        // Every pair of a track and detection of the same class that
+        // overlap enough, most overlapping first
+        let mut pairs = Vec::<(f64, usize, usize)>::new();
+        for (ti, track) in self.tracks.iter().enumerate() {
+            let rect = track.rect();
+            for (di, d) in detections.iter().enumerate() {
+                if d.label != track.label { continue }
+                let iou = rect.iou(&d.detection.rect);
+                if iou >= self.min_iou { pairs.push((iou, ti, di)) }
+            }
+        }
+        pairs.sort_by_key(|(iou, ..)| std::cmp::Reverse(OrderedF64(*iou)));
+
+        let mut track_matched = vec![false; self.tracks.len()];
+        let mut matches: Vec<Option<usize>> = vec![None; detections.len()];
+        for (_, ti, di) in pairs {
+            if track_matched[ti] || matches[di].is_some() { continue }
+            track_matched[ti] = true;
+            matches[di] = Some(ti);
+            self.tracks[ti].update(&detections[di].detection.rect);
+        }
+        for (track, matched) in self.tracks.iter_mut().zip(track_matched) {
+            if !matched { track.misses += 1 }
+        }
+
+        // Detections that match no track start new tracks
+        for (d, m) in detections.iter().zip(matches.iter_mut()) {
+            if m.is_some() { continue }
+            self.tracks.push(Track::new(self.next_id, d));
+            self.next_id += 1;
+            *m = Some(self.tracks.len() - 1);
+        }
+
+        let tracked = detections
+            .iter()
+            .zip(matches.iter())
+            .filter_map(|(d, m)| {
+                let track = &self.tracks[(*m)?];
+                if track.hits < self.min_hits { return None }
+                Some(TrackedDetection { track: track.id, labeled: d.clone() })
+            })
+            .collect();
+
+        let max_age = self.max_age;
+        self.tracks.retain(|t| t.misses <= max_age);
+        tracked
+    }
+}
+
+/// Gets the center x, center y, width and height of a rectangle
+fn coordinates(r: &Rectangle<u32>) -> [f64; 4] {
+    let [w, h] = [f64::from(r.width()), f64::from(r.height())];
+    let [x, y] = r.top_left.map(f64::from);
+    [x + w / 2.0, y + h / 2.0, w, h]
+}
+
+/// Gets the mean of the width and height of a rectangle
+fn size(r: &Rectangle<u32>) -> f64 {
+    f64::from(r.width() + r.height()) / 2.0
+}
+
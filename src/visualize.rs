@@ -0,0 +1,182 @@
+use image::imageops::{resize, FilterType};
+use image::Rgb;
+
+use super::{
+    Cascade, ColorImage, ImageData, IntegralImageTrait, Window, WindowSize,
+};
+
+/// Size in pixels of the gap between the tiles of a contact sheet
+const TILE_MARGIN: u32 = 4;
+
+/// How strongly the rectangles of features are shaded over the image
+const SHADE: f64 = 0.6;
+
+/// Renders the features of a cascade over the mean object image. The
+/// image is scaled up so each pixel of the window is a square block
+pub struct Visualizer {
+    /// The mean object image at the size of the window
+    pub mean: ColorImage,
+
+    /// The number of pixels on each side of the square drawn for each
+    /// pixel of the window
+    pub scale: u32,
+} impl Visualizer {
+    /// Averages the objects of a set of images. Images that are not the
+    /// size of the window are resized first
+    pub fn new(set: &[ImageData], window: [u32; 2], scale: u32) -> Visualizer {
+        let [wl, wh] = window;
+        let size = window.map(|s| s as WindowSize);
+        let mut sums = vec![[0u64; 3]; (wl * wh) as usize];
+        let mut count = 0;
+        for data in set.iter().filter(|d| d.is_object) {
+            let fits = data.image.width() == wl as usize
+                && data.image.height() == wh as usize;
+            let img = if fits {
+                data.image.to_colour_image()
+            } else { data.resized(size).image.to_colour_image() };
+            for (sum, pixel) in sums.iter_mut().zip(img.pixels()) {
+                (0..3).for_each(|c| sum[c] += u64::from(pixel[c]));
+            }
+            count += 1;
+        }
+
+        let count = count.max(1);
+        let mean = ColorImage::from_fn(wl, wh, |x, y| {
+            let sum = sums[(x + wl * y) as usize];
+            Rgb(sum.map(|s| (s / count) as u8))
+        });
+        Visualizer { mean, scale: scale.max(1) }
+    }
+
+    /// Gets the mean object image scaled up
+    pub fn background(&self) -> ColorImage {
+        let (w, h) = self.mean.dimensions();
+        resize(&self.mean, w * self.scale, h * self.scale, FilterType::Nearest)
+    }
+
+    /// Gets the pixels of the scaled up image covered by a rectangle.
+    /// Like the sums of an integral image, a rectangle covers the
+    /// pixels after its top left corner up to its bottom right corner
+    fn covered(&self, r: &Window) -> impl Iterator<Item = (u32, u32)> {
+        let s = self.scale;
+        let [x1, y1] = r.top_left.map(|v| (u32::from(v) + 1) * s);
+        let [x2, y2] = r.bot_right.map(|v| (u32::from(v) + 1) * s);
+        (x1..x2).flat_map(move |x| (y1..y2).map(move |y| (x, y)))
+    }
+
+    /// Draws rectangles over the mean object image, darkening black
+    /// rectangles and lightening white ones
+    pub fn draw(&self, rects: &[(Window, bool)]) -> ColorImage {
+        let mut img = self.background();
+        for (r, black) in rects.iter() {
+            let target = if *black { 0.0 } else { 255.0 };
+            for (x, y) in self.covered(r) {
+                if x >= img.width() || y >= img.height() { continue }
+                let pixel = img.get_pixel_mut(x, y);
+                pixel.0 = pixel.0.map(|c| {
+                    (f64::from(c) * (1.0 - SHADE) + target * SHADE) as u8
+                });
+            }
+        }
+        img
+    }
+
+    /// Draws each weak learner of each stage of a cascade on its own
+    /// tile and lays out the tiles of each stage on a contact sheet
+    pub fn stages(&self, cascade: &Cascade) -> Vec<ColorImage> {
+        cascade
+            .stages()
+            .iter()
+            .map(|sc| {
+                let tiles: Vec<ColorImage> = sc
+                    .weak_learners()
+                    .map(|(wl, _)| self.draw(&wl.rectangles()))
+                    .collect();
+                contact_sheet(&tiles)
+            })
+            .collect()
+    }
+
+    /// Gets how often each pixel of the window is covered by the
+    /// features of a cascade, with each weak learner counted by the
+    /// size of its weight. The coverage is scaled so that its maximum
+    /// is 1
+    pub fn coverage(&self, cascade: &Cascade) -> Vec<f64> {
+        let (w, h) = self.mean.dimensions();
+        let mut coverage = vec![0.0; (w * h) as usize];
+        for sc in cascade.stages() {
+            for (wl, weight) in sc.weak_learners() {
+                let mut covered = vec![false; coverage.len()];
+                for (r, _) in wl.rectangles() {
+                    let [x1, y1] = r.top_left.map(|v| u32::from(v) + 1);
+                    let [x2, y2] = r.bot_right.map(|v| u32::from(v) + 1);
+                    for y in y1..y2.min(h) {
+                        for x in x1..x2.min(w) {
+                            covered[(x + w * y) as usize] = true;
+                        }
+                    }
+                }
+                coverage
+                    .iter_mut()
+                    .zip(covered.iter())
+                    .filter(|(_, c)| **c)
+                    .for_each(|(cov, _)| *cov += weight.abs());
+            }
+        }
+
+        let max = coverage.iter().copied().fold(0.0, f64::max);
+        if max > 0.0 { coverage.iter_mut().for_each(|c| *c /= max); }
+        coverage
+    }
+
+    /// Blends a heatmap of the coverage of the features of a cascade
+    /// over the mean object image in greyscale
+    pub fn heatmap(&self, cascade: &Cascade) -> ColorImage {
+        let coverage = self.coverage(cascade);
+        let (w, _) = self.mean.dimensions();
+        let mut img = self.background();
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            let i = (x / self.scale + w * (y / self.scale)) as usize;
+            let [r, g, b] = pixel.0.map(f64::from);
+            let grey = 0.299 * r + 0.587 * g + 0.114 * b;
+            let heat = heat_colour(coverage[i]).0.map(f64::from);
+            pixel.0 = heat.map(|c| (0.4 * grey + 0.6 * c) as u8);
+        }
+        img
+    }
+}
+
+/// Maps a value from 0 to 1 to a colour going from black through red
+/// and yellow to white
+pub fn heat_colour(t: f64) -> Rgb<u8> {
+    let channel = |offset: f64| {
+        ((3.0 * t - offset).clamp(0.0, 1.0) * 255.0) as u8
+    };
+    Rgb([channel(0.0), channel(1.0), channel(2.0)])
+}
+
+/// Lays out images of the same size on a white grid that is as close
+/// to square as possible
+pub fn contact_sheet(tiles: &[ColorImage]) -> ColorImage {
+    let white = Rgb([0xFF, 0xFF, 0xFF]);
+    let (w, h) = match tiles.first() {
+        Some(tile) => tile.dimensions(),
+        None => return ColorImage::from_pixel(1, 1, white),
+    };
+    let cols = (tiles.len() as f64).sqrt().ceil() as u32;
+    let rows = (tiles.len() as u32).div_ceil(cols);
+    let mut sheet = ColorImage::from_pixel(
+        cols * (w + TILE_MARGIN) + TILE_MARGIN,
+        rows * (h + TILE_MARGIN) + TILE_MARGIN,
+        white,
+    );
+    for (i, tile) in tiles.iter().enumerate() {
+        let (col, row) = (i as u32 % cols, i as u32 / cols);
+        let x = TILE_MARGIN + col * (w + TILE_MARGIN);
+        let y = TILE_MARGIN + row * (h + TILE_MARGIN);
+        for (tx, ty, pixel) in tile.enumerate_pixels() {
+            sheet.put_pixel(x + tx, y + ty, *pixel);
+        }
+    }
+    sheet
+}
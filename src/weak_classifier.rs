@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 use super::{
     new_bar, Feature, ImageData, IntegralImageTrait, OrderedF64,
-    Window, WH, WL, PERCENTAGE_TO_FILTER, Classifier,
+    Template, Window, WindowSize, Classifier, NUM_CHANNELS, WL_RATIO,
 };
 
 #[derive(Serialize, Deserialize, Debug, Copy, Clone)]
@@ -21,74 +21,59 @@ impl WeakClassifier {
         }
     }
 
-    /// Filters out a certain percentage of poor performing
-    /// weak classifiers from a vector of weak classifiers
-    pub fn filter(
-        mut wcs: Vec<WeakClassifier>,
-        set: &mut [ImageData],
-    ) -> Vec<WeakClassifier> {
-
-        if (PERCENTAGE_TO_FILTER < 0.0) || (PERCENTAGE_TO_FILTER > 100.0) {
-            panic!("PERCENTAGE_TO_FILTER must be between 0 and 100");
-        }
-
-        WeakClassifier::calculate_thresholds(&mut wcs, set);
-        wcs.sort_by_cached_key(|wc: &WeakClassifier| {
-            OrderedF64(wc.error(set))
-        });
-        wcs.reverse();
-        wcs.truncate(
-            (PERCENTAGE_TO_FILTER/100.0* (wcs.len() as f64)).ceil() as usize
-        );
-        wcs
-    }
+    /// Gets the feature evaluated by the weak classifier
+    pub fn feature(&self) -> &Feature { &self.feature }
 
     /// Calculates the optimal threshold and polarity for the weak
-    /// classifier
+    /// classifier. Weights of objects are scaled by the cost ratio
     pub fn calculate_threshold(
         &mut self,
         set: &[ImageData],
         afs: f64,
         abg: f64,
+        cost_ratio: f64,
     ) {
         // Sort the training images based on it's evaluation
-        let mut sorted: Vec<_> = set.iter().collect();
-        sorted.sort_unstable_by(|a: &&ImageData, b: &&ImageData| {
-            let a_eval = self.feature.evaluate(&a.image);
-            let b_eval = self.feature.evaluate(&b.image);
-            a_eval.cmp(&b_eval)
-        });
+        let mut sorted: Vec<(i64, &ImageData)> = set
+            .iter()
+            .map(|data| (self.feature.evaluate(&data.image), data))
+            .collect();
+        sorted.sort_unstable_by_key(|(eval, _)| *eval);
 
         // Set up variables used in the following loop
-        let mut cf: usize = 0; // Total number of pos samples seen
-        let mut cg: usize = 0; // Total number of neg samples seen
         let mut fs: f64 = 0.0; // Sum of the weights of pos samples seen
         let mut bg: f64 = 0.0; // Sum of the weights of neg samples seen
-        let mut min_err: f64 = 1.0; // The minimum value of the error
-        let mut best_image = &set[0].image;
+        let mut min_err: f64 = f64::INFINITY; // The minimum error
 
-        for data in set.iter() {
+        for (i, (eval, data)) in sorted.iter().enumerate() {
             // Add the weight to fs/bg
             if data.is_object {
-                fs += data.weight;
-                cf += 1;
+                fs += data.cost(cost_ratio);
             } else {
                 bg += data.weight;
-                cg += 1;
             }
 
-            // Compute the error function
-            let err = f64::min(bg + afs - fs, fs + abg - bg);
+            // Only split between different evaluations, since the
+            // threshold puts every image with the same evaluation on
+            // the same side
+            if sorted.get(i + 1).is_some_and(|(next, _)| next == eval) {
+                continue;
+            }
+
+            // Compute the error of labeling the images seen so far
+            // as objects and as others
+            let below_pos_err = bg + afs - fs;
+            let below_neg_err = fs + abg - bg;
+            let err = f64::min(below_pos_err, below_neg_err);
 
             // If we found a threshold with less error update
             // min_err and the polarity
             if err < min_err {
                 min_err = err;
-                best_image = &data.image;
-                self.pos_polarity = cf > cg;
+                self.threshold = eval + 1;
+                self.pos_polarity = below_pos_err < below_neg_err;
             }
         }
-        self.threshold = self.feature.evaluate(best_image);
     }
 
     /// Calculate the optimal thresholds for a slice of weak
@@ -97,12 +82,13 @@ impl WeakClassifier {
     pub fn calculate_thresholds(
         wcs: &mut [WeakClassifier],
         set: &[ImageData],
+        cost_ratio: f64,
     ){
         // Calculate the optimal thresholds for all weak classifiers
         let afs = set
             .iter()
             .filter(|data| data.is_object)
-            .map(|data| data.weight)
+            .map(|data| data.cost(cost_ratio))
             .sum();
         let abg = set
             .iter()
@@ -112,110 +98,59 @@ impl WeakClassifier {
         let bar =
             new_bar(wcs.len() as u64, "Calculating Thresholds...");
         wcs.par_iter_mut().for_each(|wc| {
-            wc.calculate_threshold(set, afs, abg);
+            wc.calculate_threshold(set, afs, abg, cost_ratio);
             bar.inc(1);
         });
         bar.finish();
     }
 
-    /// Gets all possible weak classifiers
-    pub fn get_all() -> Vec<WeakClassifier> {
-        let mut wcs = Vec::<WeakClassifier>::with_capacity(200_000);
-        for w in 1..(WL + 1) {
-            for h in 1..(WH + 1) {
-                let mut i = 0;
-                while (i + w) < WL {
-                    let mut j = 0;
-                    while (j + h) < WH {
-                        // Horizontal Two Rectangle Features
-                        if (i + 2 * w) < WL {
-                            let white =
-                                (Window::new(i, j, w, h), None);
-                            let black =
-                                (Window::new(i + w, j, w, h), None);
-                            let wc = WeakClassifier::new(Feature {
-                                white,
-                                black,
-                            });
-                            wcs.push(wc);
-                        }
-
-                        // Vertical Two Rectangle Feature
-                        if (j + 2 * h) < WH {
-                            let white =
-                                (Window::new(i, j, w, h), None);
-                            let black =
-                                (Window::new(i, j + h, w, h), None);
-                            let wc = WeakClassifier::new(Feature {
-                                white,
-                                black,
-                            });
-                            wcs.push(wc);
-                        }
-
-                        // Horizontal Three Rectangle Feature
-                        if (i + 3 * w) < WL {
-                            let white = (
-                                Window::new(i, j, w, h),
-                                Some(Window::new(i + 2 * w, j, w, h)),
-                            );
-                            let black =
-                                (Window::new(i + w, j, w, h), None);
-                            let wc = WeakClassifier::new(Feature {
-                                white,
-                                black,
-                            });
-                            wcs.push(wc);
-                        }
-
-                        // Vertical Three Rectangle Feature
-                        if (j + 3 * h) < WH {
-                            let white = (
-                                Window::new(i, j, w, h),
-                                Some(Window::new(i, j + 2 * h, w, h)),
-                            );
-                            let black =
-                                (Window::new(i, j + h, w, h), None);
-                            let wc = WeakClassifier::new(Feature {
-                                white,
-                                black,
-                            });
-                            wcs.push(wc);
-                        }
+    /// Gets a weak classifier for every feature of a list of templates
+    /// that fits in a window
+    pub fn get_all(
+        templates: &[Template],
+        window: [WindowSize; 2],
+    ) -> Vec<WeakClassifier> {
+        templates
+            .iter()
+            .flat_map(|t| t.features(window))
+            .map(WeakClassifier::new)
+            .collect()
+    }
 
-                        // Four rectangle features
-                        if (i + 2 * w) < WL && (j + 2 * h) < WH {
-                            let white = (
-                                Window::new(i, j, w, h),
-                                Some(Window::new(i + w, j + h, w, h)),
-                            );
-                            let black = (
-                                Window::new(i + w, j, w, h),
-                                Some(Window::new(i, j + h, w, h)),
-                            );
-                            let wc = WeakClassifier::new(Feature {
-                                white,
-                                black,
-                            });
-                            wcs.push(wc);
+    /// Gets a weak classifier for the sum of every channel over every
+    /// rectangle on a grid of cells the size of the window scale
+    pub fn get_all_channels(window: [WindowSize; 2]) -> Vec<WeakClassifier> {
+        let [wl, wh] = window;
+        let step = (wl / WL_RATIO).max(1);
+        let mut wcs = Vec::<WeakClassifier>::new();
+        for w in (step..wl).step_by(usize::from(step)) {
+            for h in (step..wh).step_by(usize::from(step)) {
+                for x in (0..(wl - w)).step_by(usize::from(step)) {
+                    for y in (0..(wh - h)).step_by(usize::from(step)) {
+                        let rect = Window::new(x, y, w, h);
+                        for channel in 0..NUM_CHANNELS as u8 {
+                            let feature = Feature::channel_sum(channel, rect);
+                            wcs.push(WeakClassifier::new(feature));
                         }
-                        j += 1;
                     }
-                    i += 1;
                 }
             }
         }
         wcs
     }
 
-    /// Calculates the error of a wc over a given training set
-    pub fn error(&self, set: &[ImageData]) -> f64 {
+    /// Calculates the error of a wc over a given training set. Weights
+    /// of objects are scaled by the cost ratio and the error is a
+    /// fraction of the total scaled weight
+    pub fn error(&self, set: &[ImageData], cost_ratio: f64) -> f64 {
+        let total: f64 = set.iter().map(|data| data.cost(cost_ratio)).sum();
         set.iter()
             .filter(|data| {
                 data.is_object != self.classify(&data.image)
             })
-            .map(|data| data.weight)
+            .map(|data| data.cost(cost_ratio))
             .sum::<f64>()
+            / total
     }
 
     /// Gets the weak classifier that performs best over a 
@@ -223,25 +158,37 @@ impl WeakClassifier {
     pub fn get_best(
         wcs: &[WeakClassifier],
         set: &[ImageData],
+        cost_ratio: f64,
     ) -> WeakClassifier {
         // Find the best weak classifier
         wcs.iter()
-            .min_by_key(|wc| OrderedF64(wc.error(set)))
+            .min_by_key(|wc| OrderedF64(wc.error(set, cost_ratio)))
+            .copied()
             .expect("List of weak classifiers was empty")
-            .clone()
     }
 
     /// Updates the weights of the images based off of self's
-    /// error over a set of images
-    pub fn update_weights(&self, set: &mut [ImageData]) -> f64 {
-        let err = self.error(set);
+    /// error over a set of images. Correctly classified images lose
+    /// weight and misclassified images gain weight, asymmetrically if
+    /// the cost ratio is not 1 (see ImageData::reweight())
+    pub fn update_weights(
+        &self,
+        set: &mut [ImageData],
+        cost_ratio: f64,
+    ) -> f64 {
+        // Keep the error above zero so a perfect weak classifier
+        // does not get an infinite weight
+        let err = f64::max(self.error(set, cost_ratio), 1e-10);
         let beta_t = err / (1.0 - err);
+        let alpha = f64::ln(1.0 / beta_t);
 
         // Update the weights
-        set.iter_mut()
-            .filter(|data| self.classify(&data.image) == data.is_object)
-            .for_each(|data| {data.weight *= beta_t;});
-        f64::ln(1.0 / beta_t)
+        for data in set.iter_mut() {
+            let correct = self.classify(&data.image) == data.is_object;
+            let margin = if correct { alpha / 2.0 } else { -alpha / 2.0 };
+            data.reweight(margin, cost_ratio);
+        }
+        alpha
     }
 
 } impl Classifier for WeakClassifier {
@@ -249,3 +196,65 @@ impl WeakClassifier {
         self.pos_polarity == (self.feature.evaluate(img) < self.threshold)
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{GreyscaleImage, IntegralImage};
+    use image::Luma;
+
+    /// Makes a training image whose feature (see feature()) evaluates to
+    /// a value
+    pub fn data(value: u8, is_object: bool, weight: f64) -> ImageData {
+        let img = GreyscaleImage::from_pixel(2, 2, Luma([value]));
+        ImageData { image: IntegralImage::from(&img), weight, is_object }
+    }
+
+    /// A feature that is the value of the bottom right pixel of an image
+    fn feature() -> Feature {
+        Feature {
+            black: (Window::new(0, 0, 1, 1), None),
+            white: (Window::new(0, 0, 0, 0), None),
+            channel: None,
+        }
+    }
+
+    /// Fits a weak classifier to the evaluations of a set of images
+    pub fn fit(set: &[ImageData]) -> WeakClassifier {
+        let mut wc = WeakClassifier::new(feature());
+        let wcs = std::slice::from_mut(&mut wc);
+        WeakClassifier::calculate_thresholds(wcs, set, 1.0);
+        wc
+    }
+
+    #[test]
+    fn threshold_search_walks_images_in_order() {
+        // Objects and others are interleaved in the set but separable
+        // by their evaluations
+        let set: Vec<ImageData> = [9, 1, 7, 3, 8, 2]
+            .iter()
+            .map(|&v| data(v, v < 5, 1.0 / 6.0))
+            .collect();
+        let wc = fit(&set);
+        assert_eq!(wc.error(&set, 1.0), 0.0);
+        assert!(set.iter().all(|d| wc.classify(&d.image) == d.is_object));
+    }
+
+    #[test]
+    fn threshold_search_keeps_equal_evaluations_together() {
+        // One object and two others evaluate to 3. Splitting after the
+        // object looks perfect but the threshold cannot separate them,
+        // so the best split is below 3, missing that object
+        let set = [(1, true), (3, true), (3, false), (3, false), (5, false)];
+        for shift in 0..set.len() {
+            let mut rotated = set.to_vec();
+            rotated.rotate_left(shift);
+            let set: Vec<ImageData> = rotated
+                .iter()
+                .map(|&(v, is_object)| data(v, is_object, 0.2))
+                .collect();
+            let wc = fit(&set);
+            assert!((wc.error(&set, 1.0) - 0.2).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,564 @@
+use std::time::Instant;
+
+use rand::{rngs::StdRng, seq::SliceRandom};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    new_bar, select_pool, Boosting, Classifier, DecisionTree, Feature,
+    FeatureFamily, HogFeature, HogStump, ImageData, IntegralImageTrait,
+    LbpFeature, LbpStump, OrderedF64, TrainingConfig, WeakClassifier,
+    Window, REAL_BINS,
+};
+
+/// The features that weak learners can be built from
+pub struct FeaturePool {
+    /// Potential weak classifiers of Haar-like and channel features
+    pub wcs: Vec<WeakClassifier>,
+
+    /// Potential multi-block LBP features
+    pub lbp: Vec<LbpFeature>,
+
+    /// Potential HOG features
+    pub hog: Vec<HogFeature>,
+} impl FeaturePool {
+    /// Gets every feature of the families used by a training config.
+    /// Weak classifiers of Haar-like and channel features are selected
+    /// from with the strategies of the config
+    pub fn new(config: &TrainingConfig, set: &[ImageData]) -> FeaturePool {
+        let wcs = FeaturePool::get_wcs(config);
+        let wcs = if wcs.is_empty() { wcs } else { select_pool(wcs, set, config) };
+        let lbp = if config.uses(FeatureFamily::Lbp) {
+            let lbp = LbpFeature::get_all(config.window());
+            println!("Found {} possible LBP features", lbp.len());
+            lbp
+        } else { Vec::new() };
+        let hog = if config.uses(FeatureFamily::Hog) {
+            let hog = HogFeature::get_all(config.window());
+            println!("Found {} possible HOG features", hog.len());
+            hog
+        } else { Vec::new() };
+        FeaturePool { wcs, lbp, hog }
+    }
+
+    /// Prints the number of features of each template and family used
+    /// by a training config, before any are selected
+    pub fn print_counts(config: &TrainingConfig) {
+        let window = config.window();
+        let mut counts = Vec::<(String, usize)>::new();
+        if config.uses(FeatureFamily::Haar) {
+            for t in config.templates.iter() {
+                counts.push((t.shape.name(), t.count(window)));
+            }
+        }
+        if config.uses(FeatureFamily::Channels) {
+            let n = WeakClassifier::get_all_channels(window).len();
+            counts.push(("channels".to_owned(), n));
+        }
+        if config.uses(FeatureFamily::Lbp) {
+            counts.push(("lbp".to_owned(), LbpFeature::get_all(window).len()));
+        }
+        if config.uses(FeatureFamily::Hog) {
+            counts.push(("hog".to_owned(), HogFeature::get_all(window).len()));
+        }
+
+        println!("{:-^30}", " Feature Pool ");
+        println!("Window: {}x{}", window[0], window[1]);
+        for (name, n) in counts.iter() {
+            println!("{:>22}: {}", name, n);
+        }
+        let total: usize = counts.iter().map(|(_, n)| n).sum();
+        println!("{:>22}: {}", "total", total);
+    }
+
+    /// Gets every potential weak classifier of the Haar-like and
+    /// channel features used by a training config
+    pub fn get_wcs(config: &TrainingConfig) -> Vec<WeakClassifier> {
+        let mut wcs = Vec::<WeakClassifier>::new();
+        if config.uses(FeatureFamily::Haar) {
+            println!("{:-^30}", " Getting Weak Classifiers ");
+            wcs = WeakClassifier::get_all(&config.templates, config.window());
+            println!("Found {} possible weak classifiers", wcs.len());
+        }
+        if config.uses(FeatureFamily::Channels) {
+            let channels = WeakClassifier::get_all_channels(config.window());
+            println!("Found {} possible channel features", channels.len());
+            wcs.extend(channels);
+        }
+        wcs
+    }
+}
+
+/// A weak learner that outputs a real value for an image. Strong
+/// classifiers sum the weighted outputs of their weak learners
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum WeakLearner {
+    /// Outputs 1 for objects and 0 for others (Discrete AdaBoost)
+    Discrete(WeakClassifier),
+
+    /// Outputs a real value on each side of a threshold (Gentle
+    /// AdaBoost)
+    Regression(RegressionStump),
+
+    /// Outputs a real value for each bin of feature values (Real
+    /// AdaBoost)
+    Binned(BinnedStump),
+
+    /// Outputs the value of the leaf of a decision tree
+    Tree(DecisionTree),
+
+    /// Outputs a value for each code of a multi-block LBP feature
+    Lbp(LbpStump),
+
+    /// Outputs a value on each side of a threshold of a HOG feature
+    Hog(HogStump),
+} impl WeakLearner {
+    /// Chooses the best weak learner for the boosting algorithm of a
+    /// training config using the features of a pool. Updates the
+    /// weights of the images and returns the weak learner along with
+    /// its weight in the strong classifier.
+    ///
+    /// If the config trims weights, the images with the lowest weights
+    /// are ignored when choosing the weak learner, and if it subsamples
+    /// features, only a random fraction of the features are
+    /// considered. The weights of all images are still updated
+    pub fn boost(
+        config: &TrainingConfig,
+        pool: &mut FeaturePool,
+        set: &mut [ImageData],
+        rng: &mut StdRng,
+    ) -> (WeakLearner, f64) {
+        let start = Instant::now();
+        let keep = config.trim
+            .map_or(set.len(), |quantile| ImageData::trim(set, quantile));
+        let sample_size = |len: usize| {
+            config.feature_fraction.map_or(len, |f| {
+                ((f * len as f64).ceil() as usize).clamp(len.min(1), len)
+            })
+        };
+        let num_wcs = sample_size(pool.wcs.len());
+        let num_lbp = sample_size(pool.lbp.len());
+        let num_hog = sample_size(pool.hog.len());
+        let (wcs, _) = pool.wcs.partial_shuffle(rng, num_wcs);
+        let (lbp, _) = pool.lbp.partial_shuffle(rng, num_lbp);
+        let (hog, _) = pool.hog.partial_shuffle(rng, num_hog);
+
+        let wl = WeakLearner::choose(config, wcs, lbp, hog, &set[..keep]);
+        println!(
+            "Chose from {} of {} images and {} of {} features in {:.2?} \
+            with error {:.6}",
+            keep, set.len(), num_wcs + num_lbp + num_hog,
+            pool.wcs.len() + pool.lbp.len() + pool.hog.len(),
+            start.elapsed(), wl.error(set),
+        );
+        let weight = match (&wl, config.boosting) {
+            (WeakLearner::Discrete(wc), _) => {
+                wc.update_weights(set, config.cost_ratio)
+            },
+            (_, Boosting::Discrete) => {
+                wl.update_discrete_weights(set, config.cost_ratio)
+            },
+            _ => {
+                wl.update_weights(set, config.cost_ratio);
+                1.0
+            },
+        };
+        (wl, weight)
+    }
+
+    /// Chooses the best weak learner over a set of images without
+    /// updating their weights. The best weak learners of each family
+    /// of features are compared by the loss of the boosting algorithm
+    fn choose(
+        config: &TrainingConfig,
+        wcs: &mut [WeakClassifier],
+        lbp: &[LbpFeature],
+        hog: &[HogFeature],
+        set: &[ImageData],
+    ) -> WeakLearner {
+        let mut candidates = Vec::<WeakLearner>::new();
+        if !wcs.is_empty() {
+            candidates.push(WeakLearner::choose_from_wcs(config, wcs, set));
+        }
+        if !lbp.is_empty() {
+            candidates.push(WeakLearner::get_best_lbp(config, lbp, set));
+        }
+        if !hog.is_empty() {
+            candidates.push(WeakLearner::get_best_hog(config, hog, set));
+        }
+        if candidates.len() == 1 { return candidates.remove(0) }
+        candidates
+            .into_iter()
+            .min_by_key(|wl| {
+                OrderedF64(wl.loss(config.boosting, set, config.cost_ratio))
+            })
+            .expect("No features to choose weak learners from")
+    }
+
+    /// Chooses the best weak learner built from the features of a list
+    /// of potential weak classifiers
+    fn choose_from_wcs(
+        config: &TrainingConfig,
+        wcs: &mut [WeakClassifier],
+        set: &[ImageData],
+    ) -> WeakLearner {
+        if let Some(depth) = config.tree_depth {
+            let tree = DecisionTree::fit(
+                config.boosting, depth, wcs, set, config.cost_ratio,
+            );
+            return WeakLearner::Tree(tree);
+        }
+
+        let cost_ratio = config.cost_ratio;
+        match config.boosting {
+            Boosting::Discrete => {
+                WeakClassifier::calculate_thresholds(wcs, set, cost_ratio);
+                let wc = WeakClassifier::get_best(wcs, set, cost_ratio);
+                WeakLearner::Discrete(wc)
+            },
+            Boosting::Gentle => {
+                WeakLearner::get_best(wcs, set, |f, set| {
+                    let (stump, err) = RegressionStump::fit(f, set, cost_ratio);
+                    (WeakLearner::Regression(stump), err)
+                })
+            },
+            Boosting::Real => {
+                WeakLearner::get_best(wcs, set, |f, set| {
+                    let (stump, z) = BinnedStump::fit(f, set, cost_ratio);
+                    (WeakLearner::Binned(stump), z)
+                })
+            },
+        }
+    }
+
+    /// Fits a weak learner to the feature of every potential weak
+    /// classifier and returns the one with the lowest loss
+    fn get_best(
+        all_wcs: &[WeakClassifier],
+        set: &[ImageData],
+        fit: impl Fn(&Feature, &[ImageData]) -> (WeakLearner, f64) + Sync,
+    ) -> WeakLearner {
+        let bar = new_bar(all_wcs.len(), "Fitting Weak Learners...");
+        let best = all_wcs
+            .par_iter()
+            .map(|wc| {
+                let fitted = fit(wc.feature(), set);
+                bar.inc(1);
+                fitted
+            })
+            .min_by_key(|(_, loss)| OrderedF64(*loss))
+            .expect("List of weak classifiers was empty");
+        bar.finish();
+        best.0
+    }
+
+    /// Fits an LBP stump to every LBP feature and returns the one with
+    /// the lowest loss
+    fn get_best_lbp(
+        config: &TrainingConfig,
+        features: &[LbpFeature],
+        set: &[ImageData],
+    ) -> WeakLearner {
+        let bar = new_bar(features.len(), "Fitting LBP Weak Learners...");
+        let (stump, _) = features
+            .par_iter()
+            .map(|f| {
+                let fitted =
+                    LbpStump::fit(f, set, config.boosting, config.cost_ratio);
+                bar.inc(1);
+                fitted
+            })
+            .min_by_key(|(_, loss)| OrderedF64(*loss))
+            .expect("List of LBP features was empty");
+        bar.finish();
+        WeakLearner::Lbp(stump)
+    }
+
+    /// Fits a HOG stump to every HOG feature and returns the one with
+    /// the lowest loss
+    fn get_best_hog(
+        config: &TrainingConfig,
+        features: &[HogFeature],
+        set: &[ImageData],
+    ) -> WeakLearner {
+        let bar = new_bar(features.len(), "Fitting HOG Weak Learners...");
+        let (stump, _) = features
+            .par_iter()
+            .map(|f| {
+                let fitted =
+                    HogStump::fit(f, set, config.boosting, config.cost_ratio);
+                bar.inc(1);
+                fitted
+            })
+            .min_by_key(|(_, loss)| OrderedF64(*loss))
+            .expect("List of HOG features was empty");
+        bar.finish();
+        WeakLearner::Hog(stump)
+    }
+
+    /// Gets the loss a boosting algorithm minimizes when choosing a
+    /// weak learner over a set of images: the weighted error for
+    /// Discrete AdaBoost, the weighted squared error for Gentle AdaBoost
+    /// and the weighted exponential loss for Real AdaBoost. The weights
+    /// of objects are scaled by the cost ratio
+    pub fn loss(
+        &self,
+        boosting: Boosting,
+        set: &[ImageData],
+        cost_ratio: f64,
+    ) -> f64 {
+        set.iter()
+            .map(|data| {
+                let h = self.output(&data.image);
+                let y = if data.is_object { 1.0 } else { -1.0 };
+                let cost = data.cost(cost_ratio);
+                match boosting {
+                    Boosting::Discrete if (h > 0.5) == data.is_object => 0.0,
+                    Boosting::Discrete => cost,
+                    Boosting::Gentle => cost * (y - h).powi(2),
+                    Boosting::Real => cost * f64::exp(-y * h),
+                }
+            })
+            .sum()
+    }
+
+    /// Gets the weighted fraction of a set of images the weak learner
+    /// misclassifies. Real valued outputs classify images as objects
+    /// when they are positive
+    pub fn error(&self, set: &[ImageData]) -> f64 {
+        let cutoff = if self.is_discrete() { 0.5 } else { 0.0 };
+        let total: f64 = set.iter().map(|data| data.weight).sum();
+        set.iter()
+            .filter(|data| (self.output(&data.image) > cutoff) != data.is_object)
+            .fold(0.0, |err, data| err + data.weight)
+            / total
+    }
+
+    /// Updates the weights of the images like
+    /// WeakClassifier::update_weights() for weak learners that output
+    /// 1 for objects and 0 for others. Returns the weight of the weak
+    /// learner
+    fn update_discrete_weights(
+        &self,
+        set: &mut [ImageData],
+        cost_ratio: f64,
+    ) -> f64 {
+        let is_correct = |data: &ImageData| {
+            (self.output(&data.image) > 0.5) == data.is_object
+        };
+        let total: f64 = set.iter().map(|data| data.cost(cost_ratio)).sum();
+        let err: f64 = set
+            .iter()
+            .filter(|data| !is_correct(data))
+            .map(|data| data.cost(cost_ratio))
+            .sum();
+        let err = f64::max(err / total, 1e-10);
+        let beta_t = err / (1.0 - err);
+        let alpha = f64::ln(1.0 / beta_t);
+        for data in set.iter_mut() {
+            let margin = if is_correct(data) { alpha / 2.0 } else { -alpha / 2.0 };
+            data.reweight(margin, cost_ratio);
+        }
+        alpha
+    }
+
+    /// Multiplies the weight of each image by exp(-c * y * h(x)) where y
+    /// is 1 for objects and -1 for others, h(x) is the output and c is
+    /// the cost of the class of the image (see ImageData::reweight())
+    fn update_weights(&self, set: &mut [ImageData], cost_ratio: f64) {
+        for data in set.iter_mut() {
+            let y = if data.is_object { 1.0 } else { -1.0 };
+            let margin = y * self.output(&data.image);
+            data.reweight(margin, cost_ratio);
+        }
+    }
+
+    /// Gets the output of the weak learner for an image
+    pub fn output(&self, img: &impl IntegralImageTrait) -> f64 {
+        match self {
+            WeakLearner::Discrete(wc) => {
+                if wc.classify(img) { 1.0 } else { 0.0 }
+            },
+            WeakLearner::Regression(stump) => stump.output(img),
+            WeakLearner::Binned(stump) => stump.output(img),
+            WeakLearner::Tree(tree) => tree.output(img),
+            WeakLearner::Lbp(stump) => stump.output(img),
+            WeakLearner::Hog(stump) => stump.output(img),
+        }
+    }
+
+    /// Gets the rectangles summed by the weak learner, paired with
+    /// whether they are black. The center block of an LBP feature and
+    /// the rectangle of a HOG feature count as black
+    pub fn rectangles(&self) -> Vec<(Window, bool)> {
+        match self {
+            WeakLearner::Discrete(wc) => wc.feature().rectangles(),
+            WeakLearner::Regression(stump) => stump.feature.rectangles(),
+            WeakLearner::Binned(stump) => stump.feature.rectangles(),
+            WeakLearner::Tree(tree) => tree
+                .features()
+                .iter()
+                .flat_map(|f| f.rectangles())
+                .collect(),
+            WeakLearner::Lbp(stump) => stump.blocks(),
+            WeakLearner::Hog(stump) => vec![(stump.rect(), true)],
+        }
+    }
+
+    /// Gets the largest number of features evaluated to get the output
+    /// for an image
+    pub fn num_features(&self) -> usize {
+        match self {
+            WeakLearner::Tree(tree) => tree.depth(),
+            _ => 1,
+        }
+    }
+
+    /// Gets whether or not the output of the weak learner is a vote
+    /// for or against the object. Decision trees, LBP and HOG stumps are
+    /// only discrete when they were built with Discrete AdaBoost
+    pub fn is_discrete(&self) -> bool {
+        match self {
+            WeakLearner::Discrete(_) => true,
+            WeakLearner::Tree(tree) => tree.is_discrete(),
+            WeakLearner::Lbp(stump) => stump.is_discrete(),
+            WeakLearner::Hog(stump) => stump.is_discrete(),
+            _ => false,
+        }
+    }
+}
+
+/// A regression stump that outputs the weighted mean label of the
+/// training images on each side of a threshold
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RegressionStump {
+    feature: Feature,
+    threshold: i64,
+    below: f64,
+    above: f64,
+} impl RegressionStump {
+    /// Fits a regression stump to a feature by minimizing the weighted
+    /// squared error with the weights of objects scaled by the cost
+    /// ratio. Returns the stump and its error
+    pub fn fit(
+        feature: &Feature,
+        set: &[ImageData],
+        cost_ratio: f64,
+    ) -> (RegressionStump, f64) {
+        let mut sorted: Vec<(i64, &ImageData)> = set
+            .iter()
+            .map(|data| (feature.evaluate(&data.image), data))
+            .collect();
+        sorted.sort_unstable_by_key(|(eval, _)| *eval);
+
+        // Sums of the weights and the weighted labels
+        let label = |d: &ImageData| if d.is_object { 1.0 } else { -1.0 };
+        let weight = |d: &ImageData| d.cost(cost_ratio);
+        let total_w: f64 = set.iter().map(weight).sum();
+        let total_wy: f64 = set.iter().map(|d| weight(d) * label(d)).sum();
+
+        // Sweep the threshold from the lowest evaluation upwards. The
+        // weighted squared error of a split is the total weight minus
+        // the sum of (wy)^2 / w on each side
+        let mean = |wy: f64, w: f64| if w > 0.0 { wy / w } else { 0.0 };
+        let mut stump = RegressionStump {
+            feature: *feature,
+            threshold: i64::MIN,
+            below: 0.0,
+            above: mean(total_wy, total_w),
+        };
+        let mut min_err = total_w - total_wy * stump.above;
+        let (mut w, mut wy) = (0.0, 0.0);
+        for (i, (eval, data)) in sorted.iter().enumerate() {
+            w += weight(data);
+            wy += weight(data) * label(data);
+
+            // Only split between different evaluations
+            if sorted.get(i + 1).is_some_and(|(next, _)| next == eval) {
+                continue;
+            }
+            let (below, above) = (mean(wy, w), mean(total_wy - wy, total_w - w));
+            let err = total_w - wy * below - (total_wy - wy) * above;
+            if err < min_err {
+                min_err = err;
+                stump.threshold = eval + 1;
+                stump.below = below;
+                stump.above = above;
+            }
+        }
+        (stump, min_err)
+    }
+
+    pub fn output(&self, img: &impl IntegralImageTrait) -> f64 {
+        if self.feature.evaluate(img) < self.threshold {
+            self.below
+        } else { self.above }
+    }
+}
+
+/// A stump that splits feature values into equal width bins and outputs
+/// half of the log ratio of the weights of objects and others in each
+/// bin
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BinnedStump {
+    feature: Feature,
+    min: i64,
+    bin_width: i64,
+    outputs: Vec<f64>,
+} impl BinnedStump {
+    /// Fits a binned stump to a feature with the weights of objects
+    /// scaled by the cost ratio. Returns the stump and its
+    /// normalization factor Z, which bounds the training error
+    pub fn fit(
+        feature: &Feature,
+        set: &[ImageData],
+        cost_ratio: f64,
+    ) -> (BinnedStump, f64) {
+        let evals: Vec<i64> = set
+            .iter()
+            .map(|data| feature.evaluate(&data.image))
+            .collect();
+        let min = evals.iter().copied().min().unwrap_or(0);
+        let max = evals.iter().copied().max().unwrap_or(0);
+        let bins = REAL_BINS as i64;
+        let bin_width = ((max - min) / bins + 1).max(1);
+
+        let mut stump = BinnedStump {
+            feature: *feature,
+            min,
+            bin_width,
+            outputs: vec![0.0; REAL_BINS],
+        };
+
+        // Sum the weights of the objects and others in each bin
+        let mut pos = [0.0; REAL_BINS];
+        let mut neg = [0.0; REAL_BINS];
+        for (eval, data) in evals.iter().zip(set.iter()) {
+            let bin = stump.bin(*eval);
+            if data.is_object {
+                pos[bin] += data.cost(cost_ratio);
+            } else {
+                neg[bin] += data.weight;
+            }
+        }
+
+        // Smooth the outputs so empty bins do not output infinity
+        let eps = 1.0 / set.len() as f64;
+        let mut z = 0.0;
+        for bin in 0..REAL_BINS {
+            stump.outputs[bin] = 0.5 * f64::ln((pos[bin] + eps) / (neg[bin] + eps));
+            z += 2.0 * f64::sqrt(pos[bin] * neg[bin]);
+        }
+        (stump, z)
+    }
+
+    fn bin(&self, eval: i64) -> usize {
+        ((eval - self.min) / self.bin_width)
+            .clamp(0, self.outputs.len() as i64 - 1) as usize
+    }
+
+    pub fn output(&self, img: &impl IntegralImageTrait) -> f64 {
+        self.outputs[self.bin(self.feature.evaluate(img))]
+    }
+}
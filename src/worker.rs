@@ -0,0 +1,144 @@
+use std::any::Any;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::str;
+use std::time::Instant;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use super::{
+    detect_classes, Cascade, ColorImage, DetectResponse, DynamicImage, Model,
+};
+
+/// A request to detect objects in one image, read from a line of json
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WorkerRequest {
+    /// Any value, repeated in the response to match it to the request
+    id: Option<Value>,
+
+    /// The path of an image file
+    path: Option<String>,
+
+    /// The bytes of a PNG or JPEG image encoded with base64, used
+    /// instead of a path
+    image: Option<String>,
+
+    /// The maximum intersection over union of two detections of the
+    /// same class, overriding the default of the worker
+    nms: Option<f64>,
+
+    /// The classes of object to search for, every class by default
+    classes: Option<Vec<String>>,
+}
+
+/// Reads requests as lines of json from stdin and writes a line of
+/// json to stdout for each, until stdin is closed. A request holds an
+/// image as a path or as base64 and may set nms and classes, e.g.
+///   {"id": 1, "path": "img.png", "nms": 0.3, "classes": ["face"]}
+/// and is answered with the size of the image, the seconds spent and
+/// the detections, or with an error if the request could not be
+/// handled. The id and path of a request are repeated in its response.
+/// Lines that are not UTF-8 and requests that panic are answered with
+/// an error so the worker keeps going
+pub fn run(models: &[Model<Cascade>], nms: Option<f64>) {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut out = stdout.lock();
+    let mut line = Vec::<u8>::new();
+    loop {
+        line.clear();
+        let read = input
+            .read_until(b'\n', &mut line)
+            .expect("Unable to read from stdin");
+        if read == 0 { break }
+
+        let response = match str::from_utf8(&line) {
+            Ok(text) if text.trim().is_empty() => continue,
+            Ok(text) => {
+                let respond = || respond(models, nms, text);
+                panic::catch_unwind(AssertUnwindSafe(respond))
+                    .unwrap_or_else(|e| json!({
+                        "error": format!(
+                            "Unable to handle request: {}", panic_message(&e),
+                        ),
+                    }))
+            },
+            Err(e) => json!({ "error": format!("Invalid UTF-8: {}", e) }),
+        };
+        writeln!(out, "{}", response).expect("Unable to write to stdout");
+        out.flush().expect("Unable to write to stdout");
+    }
+}
+
+/// Handles a line of json and gets the response to it
+fn respond(models: &[Model<Cascade>], nms: Option<f64>, line: &str) -> Value {
+    let value: Value = match serde_json::from_str(line) {
+        Ok(value) => value,
+        Err(e) => return json!({ "error": format!("Invalid json: {}", e) }),
+    };
+
+    // The id is repeated even if the rest of the request is invalid
+    let id = value.get("id").cloned();
+    let request: WorkerRequest = match serde_json::from_value(value) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = json!({ "error": format!("Invalid request: {}", e) });
+            return with_id(error, id);
+        },
+    };
+    let response = match detect_request(models, nms, &request) {
+        Ok(detections) => {
+            let mut response = json!(detections);
+            if let Some(path) = &request.path {
+                response["path"] = json!(path);
+            }
+            response
+        },
+        Err(e) => json!({ "error": e }),
+    };
+    with_id(response, request.id)
+}
+
+/// Gets the message a panic was started with
+fn panic_message(payload: &Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else { "unknown error".into() }
+}
+
+/// Adds the id of a request to its response if it has one
+fn with_id(mut response: Value, id: Option<Value>) -> Value {
+    if let Some(id) = id { response["id"] = id }
+    response
+}
+
+/// Detects objects in the image of a request
+fn detect_request(
+    models: &[Model<Cascade>],
+    nms: Option<f64>,
+    request: &WorkerRequest,
+) -> Result<DetectResponse, String> {
+    let start = Instant::now();
+    let bytes = match (&request.path, &request.image) {
+        (Some(path), None) => fs::read(path)
+            .map_err(|e| format!("Unable to read {}: {}", path, e))?,
+        (None, Some(image)) => STANDARD
+            .decode(image.trim())
+            .map_err(|e| format!("Invalid base64 image: {}", e))?,
+        (Some(_), Some(_)) => {
+            return Err("Give either a path or an image, not both".into())
+        },
+        (None, None) => return Err("Give a path or an image".into()),
+    };
+    let img = ColorImage::from(DynamicImage::from_bytes(&bytes)?);
+    Ok(detect_classes(
+        models, &img, request.nms.or(nms), request.classes.as_deref(), start,
+    ))
+}
@@ -0,0 +1,69 @@
+//! Helpers shared by the tests that run the object-detection binary
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::OnceLock;
+
+use image::{ImageOutputFormat, Rgb, RgbImage};
+
+pub const BIN: &str = env!("CARGO_BIN_EXE_object-detection");
+
+/// Trains a one stage cascade that finds red squares, once for every
+/// test, and gets its path. Each test file trains its own in a
+/// directory named after it, so that they can run at the same time
+pub fn cascade() -> &'static Path {
+    static CASCADE: OnceLock<PathBuf> = OnceLock::new();
+    CASCADE.get_or_init(|| {
+        let dir = Path::new(env!("CARGO_TARGET_TMPDIR"))
+            .join(env!("CARGO_CRATE_NAME"));
+        let objects = dir.join("images/training/object");
+        let other = dir.join("images/training/other");
+        fs::create_dir_all(&objects).unwrap();
+        fs::create_dir_all(&other).unwrap();
+        fs::create_dir_all(dir.join("images/training/to_slice")).unwrap();
+        for i in 0..10 {
+            let shade = 20 * i as u8;
+            let object = scene(14, 16, &[[2, 2, 10, 12]], shade);
+            object.save(objects.join(format!("{}.png", i))).unwrap();
+            let background = scene(14, 16, &[], shade);
+            background.save(other.join(format!("{}.png", i))).unwrap();
+        }
+
+        let path = dir.join("cascade.json");
+        let status = Command::new(BIN)
+            .current_dir(&dir)
+            .args(["cascade", "--objects", "images/training/object"])
+            .args(["--ws", "1", "--max-stages", "1", "--layout", "1"])
+            .args(["--output", path.to_str().unwrap()])
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success(), "Unable to train cascade");
+        path
+    })
+}
+
+/// Draws red squares on a grey background
+pub fn scene(
+    width: u32,
+    height: u32,
+    squares: &[[u32; 4]],
+    shade: u8,
+) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| {
+        let inside = squares.iter().any(|&[left, top, right, bot]| {
+            (left..right).contains(&x) && (top..bot).contains(&y)
+        });
+        if inside { Rgb([220, 30, 30]) } else { Rgb([shade, shade, shade]) }
+    })
+}
+
+/// Encodes an image as a PNG file
+pub fn png(img: &RgbImage) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    image::DynamicImage::ImageRgb8(img.clone())
+        .write_to(&mut bytes, ImageOutputFormat::Png)
+        .unwrap();
+    bytes
+}
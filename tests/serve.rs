@@ -0,0 +1,163 @@
+//! Runs the serve subcommand on localhost and checks its endpoints
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+use serde_json::Value;
+
+mod common;
+
+use common::{cascade, png, scene, BIN};
+
+/// A server running in a child process that is stopped when dropped
+struct Server {
+    child: Child,
+    address: String,
+} impl Server {
+    /// Starts a server for the trained cascade under two names on any
+    /// free port
+    fn start(args: &[&str]) -> Server {
+        let cascade = cascade().to_str().unwrap().to_owned();
+        let mut child = Command::new(BIN)
+            .args(["serve", "--port", "0"])
+            .args(["--cascade", &format!("red={}", cascade)])
+            .args(["--cascade", &format!("square={}", cascade)])
+            .args(args)
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Unable to start server");
+
+        // The first line holds the address, and the rest is the log of
+        // requests which is read so the server never blocks on it
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        stdout.read_line(&mut line).unwrap();
+        let address = line
+            .trim()
+            .strip_prefix("Listening on http://")
+            .unwrap_or_else(|| panic!("Unexpected output: {}", line))
+            .to_owned();
+        thread::spawn(move || io::copy(&mut stdout, &mut io::sink()));
+
+        Server { child, address }
+    }
+
+    /// Sends a request and gets the status and json body of the response.
+    /// HTTP/1.0 keeps large responses from being sent in chunks
+    fn request(&self, method: &str, path: &str, body: &[u8]) -> (u16, Value) {
+        let mut stream = TcpStream::connect(&self.address).unwrap();
+        let head = format!(
+            "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Length: {}\r\n\r\n",
+            method, path, self.address, body.len(),
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains("Content-Type: application/json"));
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+} impl Drop for Server {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+#[test]
+fn health_counts_models() {
+    let server = Server::start(&[]);
+    let (status, body) = server.request("GET", "/health", &[]);
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["models"], 2);
+}
+
+#[test]
+fn models_describe_cascades() {
+    let server = Server::start(&[]);
+    let (status, body) = server.request("GET", "/models", &[]);
+    assert_eq!(status, 200);
+    let models = body.as_array().unwrap();
+    let labels: Vec<&str> = models
+        .iter()
+        .map(|m| m["label"].as_str().unwrap())
+        .collect();
+    assert_eq!(labels, ["red", "square"]);
+    for model in models {
+        assert_eq!(model["window"], serde_json::json!([7, 8]));
+        assert_eq!(model["num_stages"], 1);
+    }
+}
+
+#[test]
+fn detect_finds_objects() {
+    let server = Server::start(&["--nms", "0.3"]);
+    let img = scene(64, 48, &[[8, 8, 24, 26], [40, 20, 56, 38]], 40);
+    let (status, body) = server.request("POST", "/detect", &png(&img));
+    assert_eq!(status, 200);
+    assert_eq!(body["width"], 64);
+    assert_eq!(body["height"], 48);
+    let detections = body["detections"].as_array().unwrap();
+    assert!(!detections.is_empty());
+    for d in detections {
+        let right = d["rect"]["bot_right"][0].as_u64().unwrap();
+        let bot = d["rect"]["bot_right"][1].as_u64().unwrap();
+        assert!(right <= 64 && bot <= 48);
+        assert!(d["score"].is_number());
+    }
+
+    // Only the asked for classes are searched for
+    let (status, body) = server.request(
+        "POST", "/detect?classes=square&nms=0.5", &png(&img),
+    );
+    assert_eq!(status, 200);
+    let detections = body["detections"].as_array().unwrap();
+    assert!(!detections.is_empty());
+    assert!(detections.iter().all(|d| d["label"] == "square"));
+}
+
+#[test]
+fn detect_handles_requests_at_once() {
+    let server = Server::start(&["--threads", "4"]);
+    let img = png(&scene(32, 32, &[[4, 4, 20, 22]], 0));
+    thread::scope(|s| {
+        let handles: Vec<_> = (0..8)
+            .map(|_| s.spawn(|| server.request("POST", "/detect", &img)))
+            .collect();
+        for handle in handles {
+            let (status, body) = handle.join().unwrap();
+            assert_eq!(status, 200);
+            assert_eq!(body["width"], 32);
+        }
+    });
+}
+
+#[test]
+fn detect_rejects_bad_requests() {
+    let server = Server::start(&[]);
+    let (status, body) = server.request("POST", "/detect", b"not an image");
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("decode"));
+
+    let img = png(&scene(16, 16, &[], 0));
+    let (status, body) = server.request("POST", "/detect?size=2", &img);
+    assert_eq!(status, 400);
+    assert!(body["error"].as_str().unwrap().contains("size"));
+
+    let (status, _) = server.request("POST", "/detect?nms=high", &img);
+    assert_eq!(status, 400);
+}
+
+#[test]
+fn unknown_routes_are_errors() {
+    let server = Server::start(&[]);
+    assert_eq!(server.request("GET", "/nowhere", &[]).0, 404);
+    assert_eq!(server.request("GET", "/detect", &[]).0, 405);
+    assert_eq!(server.request("POST", "/health", &[]).0, 405);
+}
@@ -0,0 +1,133 @@
+//! Runs the worker subcommand and checks its answers to requests
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{cascade, png, scene, BIN};
+
+/// A worker running in a child process that is stopped when dropped
+struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+} impl Worker {
+    /// Starts a worker for the trained cascade
+    fn start() -> Worker {
+        let cascade = cascade().to_str().unwrap().to_owned();
+        let mut child = Command::new(BIN)
+            .args(["worker", "--cascade", &format!("red={}", cascade)])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .expect("Unable to start worker");
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Worker { child, stdin, stdout }
+    }
+
+    /// Sends a line of bytes and gets the json the worker answers with
+    fn send(&mut self, line: &[u8]) -> Value {
+        self.stdin.write_all(line).unwrap();
+        self.stdin.write_all(b"\n").unwrap();
+        self.stdin.flush().unwrap();
+
+        let mut response = String::new();
+        self.stdout.read_line(&mut response).unwrap();
+        serde_json::from_str(&response)
+            .unwrap_or_else(|e| panic!("Invalid response {}: {}", response, e))
+    }
+
+    /// Sends a request and gets the json the worker answers with
+    fn request(&mut self, request: Value) -> Value {
+        self.send(request.to_string().as_bytes())
+    }
+} impl Drop for Worker {
+    fn drop(&mut self) {
+        self.child.kill().ok();
+        self.child.wait().ok();
+    }
+}
+
+/// Gets the error of a response, which must have one
+fn error(response: &Value) -> &str {
+    response["error"]
+        .as_str()
+        .unwrap_or_else(|| panic!("Expected an error: {}", response))
+}
+
+#[test]
+fn detect_by_path_and_base64() {
+    let mut worker = Worker::start();
+    let img = scene(64, 48, &[[8, 8, 24, 26]], 40);
+    let path = cascade().with_file_name("scene.png");
+    img.save(&path).unwrap();
+    let path = path.to_str().unwrap();
+
+    let response = worker.request(json!({ "id": 1, "path": path }));
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["path"], path);
+    assert_eq!(response["width"], 64);
+    assert_eq!(response["height"], 48);
+    assert!(!response["detections"].as_array().unwrap().is_empty());
+
+    let image = STANDARD.encode(png(&img));
+    let response = worker.request(json!({ "id": "b", "image": image }));
+    assert_eq!(response["id"], "b");
+    assert_eq!(response["width"], 64);
+    assert!(!response["detections"].as_array().unwrap().is_empty());
+
+    // Only the asked for classes are searched for
+    let request = json!({ "path": path, "classes": ["blue"], "nms": 0.3 });
+    let response = worker.request(request);
+    assert!(response["detections"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn invalid_json_is_an_error() {
+    let mut worker = Worker::start();
+    assert!(error(&worker.send(b"{not json")).contains("Invalid json"));
+
+    let response = worker.request(json!({ "id": 2, "size": 3 }));
+    assert_eq!(response["id"], 2);
+    assert!(error(&response).contains("Invalid request"));
+}
+
+#[test]
+fn bad_images_are_errors() {
+    let mut worker = Worker::start();
+    let response = worker.request(json!({ "id": 3, "image": "%%%" }));
+    assert_eq!(response["id"], 3);
+    assert!(error(&response).contains("base64"));
+
+    let image = STANDARD.encode(b"not an image");
+    let response = worker.request(json!({ "image": image }));
+    assert!(error(&response).contains("decode"));
+
+    let path = cascade().with_file_name("missing.png");
+    let response = worker.request(json!({ "path": path }));
+    assert!(error(&response).contains("Unable to read"));
+
+    let response = worker.request(json!({}));
+    assert!(error(&response).contains("path or an image"));
+}
+
+#[test]
+fn worker_keeps_answering_after_errors() {
+    let mut worker = Worker::start();
+    assert!(error(&worker.send(b"\xff\xfe{}")).contains("UTF-8"));
+    error(&worker.send(b"[1, 2"));
+    error(&worker.request(json!({ "image": "%%%" })));
+
+    // Blank lines are skipped without an answer
+    worker.stdin.write_all(b"\n  \n").unwrap();
+    let image = STANDARD.encode(png(&scene(32, 32, &[[4, 4, 20, 22]], 0)));
+    let response = worker.request(json!({ "id": 4, "image": image }));
+    assert_eq!(response["id"], 4);
+    assert_eq!(response["width"], 32);
+    assert!(response.get("error").is_none());
+}